@@ -56,5 +56,65 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             format!("pub const {name} : (usize, usize, &[Color]) = ( {width}, {height}, &{name}_IMAGE );\n").as_bytes())?;
     }
 
+    write_prefabs(&Path::new(&out_dir).join("prefabs.rs"))?;
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=pngs");
+    println!("cargo:rerun-if-changed=prefabs");
+    Ok(())
+}
+
+// Structures for the generator (see src/prefab.rs), one per `prefabs/*.png`.
+// Transparent pixels are left out (None), other pixels keep their color.
+fn write_prefabs(dest_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest_path)?;
+
+    // no prefabs directory, no prefabs
+    let sorted_entries = match fs::read_dir("prefabs") {
+        Ok(read_dir) => read_dir
+            .map(|x| x.unwrap())
+            .sorted_by(|a, b| a.file_name().cmp(&b.file_name()))
+            .collect_vec(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(err) => return Err(err.into()),
+    };
+
+    output_file.write_all(
+        format!(
+            "pub const PREFABS: [Prefab; {}] = [\n",
+            sorted_entries.len()
+        )
+        .as_bytes(),
+    )?;
+    for file in sorted_entries {
+        let img = image::open(file.path())?;
+        let (width, height) = img.dimensions();
+
+        let name = file.file_name();
+        let name = name.to_str().unwrap().replace(".png", "");
+        output_file.write_all(
+            format!("    Prefab {{ name: {name:?}, width: {width}, height: {height}, pixels: &[")
+                .as_bytes(),
+        )?;
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = img.get_pixel(x, y);
+                if pixel[3] == 0 {
+                    output_file.write_all(b"None,")?;
+                    continue;
+                }
+                let (r, g, b) = (pixel[0], pixel[1], pixel[2]);
+                output_file.write_all(
+                    format!("Some(Color {{ r: {r:?}, g: {g:?}, b: {b:?} }}),").as_bytes(),
+                )?;
+            }
+        }
+        output_file.write_all(b"] },\n")?;
+    }
+    output_file.write_all(b"];\n")?;
     Ok(())
 }
@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+use rockies::GeneratorConfig;
+
+pub(crate) const USAGE: &str = "\
+Usage: rockies [OPTIONS]
+       rockies export <X>,<Y> <W>x<H> <OUT.png> [OPTIONS]
+       rockies preview <GX>,<GY> <GX>,<GY> <OUT.png> [OPTIONS]
+
+Commands:
+  export            Render the W by H cells of the world from X,Y (the top
+                    left corner) to OUT.png, one pixel per cell
+  preview           Generate the grids from the first grid index to the
+                    second (inclusive) with --seed and --generator, write
+                    them to OUT.png and print statistics per grid
+
+Options:
+  --world <DIR>     Load and save the world in DIR (created if missing)
+  --archive <FILE>  Load and save the world in a single archive FILE
+  --seed <N>        Generate new worlds from seed N (saved worlds keep theirs)
+  --generator <GEN> Generate new worlds with GEN: noise (default), flat, sky,
+                    or heightmap=<PNG> for ground shaped like the image
+  --import <PNG>@<X>,<Y>
+                    Draw PNG into the world with its top left corner at X,Y,
+                    mapping colors to the closest material (may be repeated)
+  -h, --help        Print this help";
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Args {
+    pub world: Option<PathBuf>,
+    pub archive: Option<PathBuf>,
+    pub seed: Option<u32>,
+    pub generator: Option<GeneratorConfig>,
+    pub imports: Vec<Import>,
+    pub command: Option<Command>,
+}
+
+/// Commands that run without opening the game
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    Export(Export),
+    Preview(PreviewArea),
+}
+
+/// A rectangle of the world to render, see `Game::export_png`
+#[derive(Debug, PartialEq)]
+pub(crate) struct Export {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub path: PathBuf,
+}
+
+/// A rectangle of grid indices to generate, see `Preview`
+#[derive(Debug, PartialEq)]
+pub(crate) struct PreviewArea {
+    pub first: (i32, i32),
+    pub last: (i32, i32),
+    pub path: PathBuf,
+}
+
+/// An image to draw into the world, see `Game::import_image`
+#[derive(Debug, PartialEq)]
+pub(crate) struct Import {
+    pub path: PathBuf,
+    pub x: i32,
+    pub y: i32,
+}
+
+fn parse_import(arg: &str) -> Result<Import, String> {
+    let invalid = || format!("invalid import {arg:?}, expected <PNG>@<X>,<Y>");
+    let (path, origin) = arg.rsplit_once('@').ok_or_else(invalid)?;
+    let (x, y) = parse_pair(origin, ',').ok_or_else(invalid)?;
+    Ok(Import {
+        path: PathBuf::from(path),
+        x,
+        y,
+    })
+}
+
+// Height (in cells) of the ground under the brightest columns of a heightmap
+const HEIGHTMAP_MAX_HEIGHT: u32 = 200;
+
+fn parse_generator(arg: &str) -> Result<GeneratorConfig, String> {
+    if let Some(path) = arg.strip_prefix("heightmap=") {
+        let bytes = std::fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+        return GeneratorConfig::heightmap_from_image(&bytes, HEIGHTMAP_MAX_HEIGHT)
+            .map_err(|err| format!("{path}: {err}"));
+    }
+    GeneratorConfig::from_name(arg).ok_or_else(|| format!("unknown generator: {arg}"))
+}
+
+fn parse_pair<T: std::str::FromStr>(arg: &str, separator: char) -> Option<(T, T)> {
+    let (a, b) = arg.split_once(separator)?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+fn parse_export(args: &mut impl Iterator<Item = String>) -> Result<Export, String> {
+    let usage = "export requires <X>,<Y> <W>x<H> <OUT.png>";
+    let origin = args.next().ok_or(usage)?;
+    let (x, y) = parse_pair(&origin, ',').ok_or_else(|| format!("invalid origin {origin:?}"))?;
+    let size = args.next().ok_or(usage)?;
+    let (width, height) = parse_pair(&size, 'x').ok_or_else(|| format!("invalid size {size:?}"))?;
+    let path = PathBuf::from(args.next().ok_or(usage)?);
+    Ok(Export {
+        x,
+        y,
+        width,
+        height,
+        path,
+    })
+}
+
+fn parse_preview(args: &mut impl Iterator<Item = String>) -> Result<PreviewArea, String> {
+    let usage = "preview requires <GX>,<GY> <GX>,<GY> <OUT.png>";
+    let mut grid_index = || -> Result<(i32, i32), String> {
+        let arg = args.next().ok_or(usage)?;
+        parse_pair(&arg, ',').ok_or_else(|| format!("invalid grid index {arg:?}"))
+    };
+    let (a, b) = (grid_index()?, grid_index()?);
+    let path = PathBuf::from(args.next().ok_or(usage)?);
+    Ok(PreviewArea {
+        first: (a.0.min(b.0), a.1.min(b.1)),
+        last: (a.0.max(b.0), a.1.max(b.1)),
+        path,
+    })
+}
+
+pub(crate) fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut res = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--world" => {
+                let dir = args.next().ok_or("--world requires a directory")?;
+                res.world = Some(PathBuf::from(dir));
+            }
+            "--archive" => {
+                let file = args.next().ok_or("--archive requires a file")?;
+                res.archive = Some(PathBuf::from(file));
+            }
+            "--seed" => {
+                let seed = args.next().ok_or("--seed requires a number")?;
+                let seed = seed
+                    .parse()
+                    .map_err(|err| format!("invalid seed {seed:?}: {err}"))?;
+                res.seed = Some(seed);
+            }
+            "--generator" => {
+                let generator = args.next().ok_or("--generator requires a name")?;
+                res.generator = Some(parse_generator(&generator)?);
+            }
+            "--import" => {
+                let import = args.next().ok_or("--import requires <PNG>@<X>,<Y>")?;
+                res.imports.push(parse_import(&import)?);
+            }
+            "export" if res.command.is_none() => {
+                res.command = Some(Command::Export(parse_export(&mut args)?));
+            }
+            "preview" if res.command.is_none() => {
+                res.command = Some(Command::Preview(parse_preview(&mut args)?));
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => return Err(format!("unknown argument: {arg}\n\n{USAGE}")),
+        }
+    }
+    Ok(res)
+}
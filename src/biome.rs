@@ -0,0 +1,127 @@
+use crate::material::Material;
+
+/// Regions of the noise generated world, each with its own terrain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Desert,
+    Grassland,
+    SnowyPeaks,
+    Swamp,
+}
+
+/// Terrain parameters of a biome.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiomeProps {
+    /// Top layer of the ground
+    pub surface: Material,
+    pub surface_depth: i32,
+    /// Between the surface and the rock
+    pub subsurface: Material,
+    pub subsurface_depth: i32,
+    /// Highest mountains (in cells)
+    pub amplitude: f64,
+    /// More caves the higher this is (1 is average)
+    pub cave_density: f64,
+    /// Brightness of the rock
+    pub rock_shade: f64,
+}
+
+const DESERT: BiomeProps = BiomeProps {
+    surface: Material::Sand,
+    surface_depth: 6,
+    subsurface: Material::Sand,
+    subsurface_depth: 10,
+    amplitude: 40.0,
+    cave_density: 0.7,
+    rock_shade: 1.0,
+};
+
+const GRASSLAND: BiomeProps = BiomeProps {
+    surface: Material::Grass,
+    surface_depth: 2,
+    subsurface: Material::Dirt,
+    subsurface_depth: 10,
+    amplitude: 100.0,
+    cave_density: 1.0,
+    rock_shade: 0.9,
+};
+
+const SNOWY_PEAKS: BiomeProps = BiomeProps {
+    surface: Material::Snow,
+    surface_depth: 4,
+    subsurface: Material::Stone,
+    subsurface_depth: 0,
+    amplitude: 220.0,
+    cave_density: 1.3,
+    rock_shade: 0.7,
+};
+
+const SWAMP: BiomeProps = BiomeProps {
+    surface: Material::Mud,
+    surface_depth: 8,
+    subsurface: Material::Dirt,
+    subsurface_depth: 6,
+    amplitude: 15.0,
+    cave_density: 1.6,
+    rock_shade: 0.6,
+};
+
+impl Biome {
+    pub const ALL: [Biome; 4] = [
+        Biome::Desert,
+        Biome::Grassland,
+        Biome::SnowyPeaks,
+        Biome::Swamp,
+    ];
+
+    pub fn props(self) -> &'static BiomeProps {
+        match self {
+            Biome::Desert => &DESERT,
+            Biome::Grassland => &GRASSLAND,
+            Biome::SnowyPeaks => &SNOWY_PEAKS,
+            Biome::Swamp => &SWAMP,
+        }
+    }
+
+    /// Picks a biome by a noise value in -1..1
+    pub fn from_noise(value: f64) -> Biome {
+        // perlin noise is mostly within -0.5..0.5
+        let i = ((value + 0.5) * Self::ALL.len() as f64).floor();
+        Self::ALL[(i.max(0.0) as usize).min(Self::ALL.len() - 1)]
+    }
+
+    /// The material at `depth` cells below the surface
+    pub fn material_at(self, depth: i32) -> Material {
+        let props = self.props();
+        if depth < props.surface_depth {
+            props.surface
+        } else if depth < props.surface_depth + props.subsurface_depth {
+            props.subsurface
+        } else {
+            Material::Stone
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_biome_from_noise_covers_all() {
+        let biomes: Vec<Biome> = (-10..=10)
+            .map(|i| Biome::from_noise(i as f64 / 10.0))
+            .collect();
+        for biome in Biome::ALL {
+            assert!(biomes.contains(&biome));
+        }
+    }
+
+    #[test]
+    fn test_biome_material_layers() {
+        assert_eq!(Biome::Grassland.material_at(0), Material::Grass);
+        assert_eq!(Biome::Grassland.material_at(5), Material::Dirt);
+        assert_eq!(Biome::Grassland.material_at(100), Material::Stone);
+        assert_eq!(Biome::SnowyPeaks.material_at(4), Material::Stone);
+    }
+}
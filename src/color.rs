@@ -8,7 +8,7 @@ pub struct Color {
 const U8_MAX_F: f64 = u8::MAX as f64;
 
 impl Color {
-    pub fn to_u32(&self) -> u32 {
+    pub fn to_u32(self) -> u32 {
         (self.r as u32 * 256 * 256) + (self.g as u32 * 256) + self.b as u32
     }
 
@@ -52,6 +52,14 @@ impl Color {
         }
     }
 
+    /// Squared distance between two colors, in RGB space
+    pub fn distance_sqr(self, other: Color) -> i32 {
+        [(self.r, other.r), (self.g, other.g), (self.b, other.b)]
+            .iter()
+            .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+            .sum()
+    }
+
     pub fn mix(self, tr: f64, tg: f64, tb: f64) -> Color {
         Color {
             r: ((self.r as f64 * tr).min(U8_MAX_F)) as u8,
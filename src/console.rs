@@ -27,23 +27,23 @@ pub(crate) fn prepare_terimnal(stdin_handle: &Stdin) -> termios {
     unsafe {
         let fd = stdin_handle.as_raw_fd();
         if 0 != tcgetattr(fd, &mut termios) {
-            assert!(false, "tcgetattr failed");
+            panic!("tcgetattr failed");
         }
         let prev_termios = termios;
         cfmakeraw(&mut termios);
-        if 0 != tcsetattr(fd, TCSANOW, &mut termios) {
-            assert!(false, "tcsetattr failed");
+        if 0 != tcsetattr(fd, TCSANOW, &termios) {
+            panic!("tcsetattr failed");
         }
         prev_termios
     }
 }
 
 pub(crate) fn restore_terminal(stdin_handle: &Stdin, prev_termios: termios) {
-    let mut termios = prev_termios;
+    let termios = prev_termios;
     unsafe {
         let fd = stdin_handle.as_raw_fd();
-        if 0 != tcsetattr(fd, TCSANOW, &mut termios) {
-            assert!(false, "tcsetattr failed");
+        if 0 != tcsetattr(fd, TCSANOW, &termios) {
+            panic!("tcsetattr failed");
         }
     }
 }
@@ -59,7 +59,7 @@ pub(crate) fn get_key(stdin_handle: &mut Stdin) -> Option<char> {
         return None;
     }
     let mut buf: [u8; 1] = [0];
-    stdin_handle.read(&mut buf).unwrap();
+    stdin_handle.read_exact(&mut buf).unwrap();
     Option::from(buf[0] as char)
 }
 
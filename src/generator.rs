@@ -1,52 +1,491 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use noise::permutationtable::NoiseHasher;
 use noise::Vector2;
 use noise::{core::perlin::perlin_2d, permutationtable::PermutationTable};
 
-use crate::color::Color;
+use crate::biome::Biome;
+
 use crate::inertia::Inertia;
+use crate::material::Material;
 use crate::multigrid::{CellIndex, GridIndex, UniverseGrid};
-use crate::universe::Cell;
+use crate::prefab::{Prefab, PrefabCell, PREFABS};
+use crate::universe::{Cell, CellState};
 use crate::v2::{V2i, V2};
 
-pub struct Generator {
+/// Fills freshly created grids with terrain. Generation must be a pure
+/// function of the grid index (and the generator's parameters): pristine
+/// grids are never stored, so they have to come out the same every time.
+pub trait WorldGenerator {
+    fn generate_pristine_grid(
+        &mut self,
+        grid: &mut UniverseGrid<Cell>,
+        grid_index: GridIndex,
+        width: usize,
+        height: usize,
+    );
+}
+
+/// Which generator a world uses (saved in the world header, together with
+/// the seed, so that the world can be regenerated)
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum GeneratorConfig {
+    /// Perlin noise mountains and caves, in biomes
+    #[default]
+    Noise,
+    /// Solid ground everywhere below y = grid height
+    Flat,
+    /// Nothing at all
+    Sky,
+    /// Ground with the surface at `heights[x]` above y = grid height, for x
+    /// in `0..heights.len()`, and flat elsewhere
+    Heightmap { heights: Vec<u32> },
+}
+
+impl GeneratorConfig {
+    /// The generators that need no parameters, by name
+    pub fn from_name(name: &str) -> Option<GeneratorConfig> {
+        match name {
+            "noise" => Some(GeneratorConfig::Noise),
+            "flat" => Some(GeneratorConfig::Flat),
+            "sky" => Some(GeneratorConfig::Sky),
+            _ => None,
+        }
+    }
+
+    /// The name `from_name` takes, "heightmap" for heightmaps
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorConfig::Noise => "noise",
+            GeneratorConfig::Flat => "flat",
+            GeneratorConfig::Sky => "sky",
+            GeneratorConfig::Heightmap { .. } => "heightmap",
+        }
+    }
+
+    pub fn build(&self, seed: u32) -> Box<dyn WorldGenerator> {
+        match self {
+            GeneratorConfig::Noise => Box::new(NoiseGenerator::new(seed)),
+            GeneratorConfig::Flat => Box::new(HeightmapGenerator::new(Vec::new())),
+            GeneratorConfig::Sky => Box::new(SkyGenerator),
+            GeneratorConfig::Heightmap { heights } => {
+                Box::new(HeightmapGenerator::new(heights.clone()))
+            }
+        }
+    }
+
+    /// A heightmap from an image: the brighter a column, the higher the
+    /// ground there, up to `max_height`
+    pub fn heightmap_from_image(
+        bytes: &[u8],
+        max_height: u32,
+    ) -> Result<GeneratorConfig, image::ImageError> {
+        let img = image::load_from_memory(bytes)?.to_luma8();
+        let (width, height) = img.dimensions();
+        let heights = (0..width)
+            .map(|x| {
+                let total: u32 = (0..height).map(|y| img.get_pixel(x, y)[0] as u32).sum();
+                total * max_height / (255 * height.max(1))
+            })
+            .collect();
+        Ok(GeneratorConfig::Heightmap { heights })
+    }
+}
+
+fn wall_cell(pos: V2i, material: Material, shade: f64) -> Cell {
+    Cell {
+        index: CellIndex::default(),
+        color: material.color(shade),
+        inertia: Inertia {
+            velocity: V2::zero(),
+            force: V2::zero(),
+            pos: pos.to_v2(),
+            mass: material.density(),
+            collision_stats: 0,
+        },
+        material,
+        state: CellState::at_rest(material),
+    }
+}
+
+fn put_wall(grid: &mut UniverseGrid<Cell>, pos: V2i, material: Material, shade: f64) {
+    grid.put(pos, Rc::new(RefCell::new(wall_cell(pos, material, shade))));
+}
+
+fn clear_pos(grid: &mut UniverseGrid<Cell>, pos: V2i) {
+    for cell in grid.get(pos).value.to_vec() {
+        grid.remove(pos, &cell);
+    }
+}
+
+// Low frequency: a biome spans a few grids
+const BIOME_SCALE: f64 = 0.3;
+
+// Where (and how often) an ore is found in rock
+struct OreVein {
+    material: Material,
+    // below the ground level
+    min_depth: f64,
+    // noise above which the rock is ore; lowered deeper down, down to
+    // `threshold - 0.1`
+    threshold: f64,
+}
+
+// Rarest first, they take precedence
+const ORE_VEINS: [OreVein; 4] = [
+    OreVein {
+        material: Material::Gem,
+        min_depth: 160.0,
+        threshold: 0.62,
+    },
+    OreVein {
+        material: Material::Gold,
+        min_depth: 80.0,
+        threshold: 0.58,
+    },
+    OreVein {
+        material: Material::Iron,
+        min_depth: 20.0,
+        threshold: 0.52,
+    },
+    OreVein {
+        material: Material::Coal,
+        min_depth: 0.0,
+        threshold: 0.48,
+    },
+];
+
+// Depth over which ores become more common
+const ORE_DEPTH_RANGE: f64 = 400.0;
+
+// Chance (out of 256) that a grid has a prefab
+const PREFAB_CHANCE: usize = 64;
+// Mixed into the hash of a grid index, to pick its prefab
+const PREFAB_SALT: isize = 0x9ef;
+
+// Lakes fill the dips in the surface that are walled in on both sides
+// within this many columns
+const LAKE_WINDOW: i32 = 64;
+// Shallower dips are left dry
+const LAKE_MIN_DEPTH: f64 = 3.0;
+
+// Caves and tunnels are flooded in aquifers, where the aquifer noise is above
+// the threshold, below the water table. The table is somewhere in this range
+// of depths, changing slowly along the columns.
+const AQUIFER_DEPTHS: (f64, f64) = (40.0, 400.0);
+const AQUIFER_THRESHOLD: f64 = 0.3;
+const AQUIFER_SCALE: f64 = 0.006;
+
+// Tunnels are dug by "worms" crawling from a random point in a grid below
+// the ground level. Each such grid may start this many worms:
+const WORMS_PER_GRID: usize = 2;
+// Chance (out of 256) that each of them is there
+const WORM_CHANCE: usize = 160;
+// Steps of one cell each, at most
+const WORM_LENGTH: usize = 240;
+// Worms stay within this many grids of the one they start in (shortened to
+// fit, in small grids)
+const WORM_REACH: i32 = 2;
+const WORM_MIN_RADIUS: f64 = 2.0;
+const WORM_MAX_RADIUS: f64 = 4.0;
+// How fast worms wind, and how fast their width changes, per step
+const WORM_TURN_SCALE: f64 = 0.015;
+// Mixed into the hash of a grid index, to pick its worms
+const WORM_SALT: isize = 0x3077;
+
+pub struct NoiseGenerator {
     hasher: PermutationTable,
+    // a separate noise channel for ores
+    ore_hasher: PermutationTable,
+    // and one for the worms' paths
+    worm_hasher: PermutationTable,
+}
+
+// Biome parameters of a column, blended with the neighboring grid's biome
+struct ColumnBiome {
+    biome: Biome,
+    amplitude: f64,
+    cave_density: f64,
+    rock_shade: f64,
 }
 
-impl Generator {
+impl NoiseGenerator {
     pub fn new(seed: u32) -> Self {
         Self {
             hasher: PermutationTable::new(seed),
+            ore_hasher: PermutationTable::new(seed ^ 0x0e5_0e5),
+            worm_hasher: PermutationTable::new(seed ^ 0x3077_3077),
         }
     }
 
+    // The ore (if any) replacing the rock at `pos`
+    fn ore_at(&self, pos: V2i, depth: f64) -> Option<Material> {
+        ORE_VEINS
+            .iter()
+            .enumerate()
+            .filter(|(_, vein)| depth >= vein.min_depth)
+            .find(|(i, vein)| {
+                // stretched horizontally, for vein-like clusters
+                let posv =
+                    Vector2::new(pos.x as f64 * 0.05 + *i as f64 * 100.5, pos.y as f64 * 0.12);
+                let richness = ((depth - vein.min_depth) / ORE_DEPTH_RANGE).min(1.0);
+                perlin_2d(posv, &self.ore_hasher) > vein.threshold - 0.1 * richness
+            })
+            .map(|(_, vein)| vein.material)
+    }
+
     fn generated_point(&self, pos: V2i) -> f64 {
         // Check for caverns
         let posv = pos.to_v2().cmul(0.01);
 
         // perlin_2d returns a value in (-1..1)
-        let local_seed = perlin_2d(Vector2::new(posv.x, posv.y), &self.hasher).abs()
-            * perlin_2d(Vector2::new(posv.y * 0.3, posv.x * 0.4), &self.hasher).abs();
-        local_seed
+        perlin_2d(Vector2::new(posv.x, posv.y), &self.hasher).abs()
+            * perlin_2d(Vector2::new(posv.y * 0.3, posv.x * 0.4), &self.hasher).abs()
     }
 
-    fn wall_cell(pos: V2i, color: Color) -> Cell {
-        Cell {
-            index: CellIndex::default(),
-            color: color,
-            inertia: Inertia {
-                velocity: V2::zero(),
-                force: V2::zero(),
-                pos: pos.to_v2(),
-                mass: 0,
-                elasticity: 1.0, // allow other mass to determine
-                collision_stats: 0,
-            },
+    // Each column of grids has a single biome
+    fn grid_biome(&self, grid_x: i32) -> Biome {
+        let posv = Vector2::new(grid_x as f64 * BIOME_SCALE + 0.5, 0.5);
+        Biome::from_noise(perlin_2d(posv, &self.hasher))
+    }
+
+    // Height of the mountains above the ground level, at column `x`
+    fn surface_at(&self, x: i32, width: usize) -> f64 {
+        self.generated_point(V2i::new(x, 0)) * self.column_biome(x, width).amplitude
+    }
+
+    // The prefab owned by a grid, if any, and the world position of its top
+    // left corner. The corner is always inside the owner grid; grids at the
+    // ground level get their prefab sitting on the surface, grids below it
+    // get one buried anywhere, and the sky gets none.
+    fn prefab_placement(
+        &self,
+        owner: GridIndex,
+        width: usize,
+        height: usize,
+    ) -> Option<(&'static Prefab, V2i)> {
+        let offset = owner.grid_offset;
+        if offset.y < 0 {
+            return None;
+        }
+        let hash = |salt: isize| {
+            self.hasher
+                .hash(&[offset.x as isize, offset.y as isize, PREFAB_SALT + salt])
+        };
+        if hash(0) >= PREFAB_CHANCE {
+            return None;
+        }
+        // None without any prefabs
+        let prefab = &PREFABS[hash(1).checked_rem(PREFABS.len())?];
+        if prefab.width > width || prefab.height > height {
+            return None;
+        }
+        let base_pos = owner.to_pos(width, height);
+        let x = base_pos.x + (hash(2) * width / 256) as i32;
+        let y = if offset.y == 0 {
+            // embedded a row into the ground under its middle column
+            let surface = self.surface_at(x + prefab.width as i32 / 2, width);
+            (height as i32 - surface as i32 - prefab.height as i32 + 1).max(base_pos.y)
+        } else {
+            base_pos.y + (hash(3) * height / 256) as i32
+        };
+        Some((prefab, V2i::new(x, y)))
+    }
+
+    // Prefabs may straddle grid edges, so each grid stamps the parts of its
+    // neighbors' prefabs (placed by `prefab_placement` from the neighbors'
+    // indices alone) that overlap it: every grid comes out the same no
+    // matter which of them is generated first. Prefabs are smaller than a
+    // grid, so only the adjacent grids can reach into this one.
+    fn stamp_prefabs(
+        &self,
+        grid: &mut UniverseGrid<Cell>,
+        grid_index: GridIndex,
+        width: usize,
+        height: usize,
+    ) {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let owner = GridIndex {
+                    grid_offset: grid_index.grid_offset.plus(V2i::new(dx, dy)),
+                };
+                let Some((prefab, corner)) = self.prefab_placement(owner, width, height) else {
+                    continue;
+                };
+                for y in 0..prefab.height as i32 {
+                    for x in 0..prefab.width as i32 {
+                        let pos = corner.plus(V2i::new(x, y));
+                        if !grid.is_in_bounds(pos) {
+                            continue;
+                        }
+                        match prefab.cell(V2i::new(x, y)) {
+                            PrefabCell::Keep => continue,
+                            PrefabCell::Air => clear_pos(grid, pos),
+                            PrefabCell::Wall(material, color) => {
+                                clear_pos(grid, pos);
+                                let mut cell = wall_cell(pos, material, 1.0);
+                                cell.color = color;
+                                grid.put(pos, Rc::new(RefCell::new(cell)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The altitude of the lake's surface in each of the grid's columns (or
+    // NEG_INFINITY, for no lake): the lower of the highest surface points to
+    // the left and to the right, within `LAKE_WINDOW`. This only depends on
+    // the columns around, so lakes are the same from either side of a grid
+    // edge.
+    fn lake_levels(&self, base_x: i32, width: usize) -> Vec<f64> {
+        let window = LAKE_WINDOW as usize;
+        let surfaces: Vec<f64> = (base_x - LAKE_WINDOW..base_x + width as i32 + LAKE_WINDOW)
+            .map(|x| self.surface_at(x, width))
+            .collect();
+        (0..width)
+            .map(|x| {
+                let i = x + window;
+                let max = |range: &[f64]| range.iter().cloned().fold(f64::MIN, f64::max);
+                let level = max(&surfaces[x..=i]).min(max(&surfaces[i..=i + window]));
+                if level - surfaces[i] < LAKE_MIN_DEPTH {
+                    f64::NEG_INFINITY
+                } else {
+                    level
+                }
+            })
+            .collect()
+    }
+
+    // The depth below which caves are flooded in column `x`
+    fn water_table(&self, x: i32) -> f64 {
+        let (min, max) = AQUIFER_DEPTHS;
+        let posv = Vector2::new(500.5, x as f64 * AQUIFER_SCALE * 0.5);
+        min + (max - min) * (perlin_2d(posv, &self.ore_hasher) + 1.0) / 2.0
+    }
+
+    // Whether a cave or tunnel at `pos` is flooded
+    fn is_flooded(&self, pos: V2i, depth: f64, water_table: f64) -> bool {
+        let posv = pos.to_v2().cmul(AQUIFER_SCALE);
+        depth >= water_table
+            && perlin_2d(Vector2::new(posv.x + 500.5, posv.y), &self.ore_hasher) > AQUIFER_THRESHOLD
+    }
+
+    // The paths (centers and radii) of the worms starting in a grid
+    fn worms(&self, owner: GridIndex, width: usize, height: usize) -> Vec<Vec<(V2, f64)>> {
+        let offset = owner.grid_offset;
+        if offset.y < 1 {
+            return Vec::new();
+        }
+        let base_pos = owner.to_pos(width, height).to_v2();
+        (0..WORMS_PER_GRID)
+            .filter_map(|i| {
+                let hash = |salt: isize| {
+                    self.hasher.hash(&[
+                        offset.x as isize,
+                        offset.y as isize,
+                        WORM_SALT + salt,
+                        i as isize,
+                    ]) as f64
+                        / 256.0
+                };
+                if hash(0) >= WORM_CHANCE as f64 / 256.0 {
+                    return None;
+                }
+                let mut pos =
+                    base_pos.plus(V2::new(hash(1) * width as f64, hash(2) * height as f64));
+                let heading = hash(3) * std::f64::consts::TAU;
+                // each worm follows its own line through the noise
+                let lane =
+                    (offset.x as f64 * 31.7 + offset.y as f64 * 17.3 + i as f64 * 5.1) % 1000.0;
+                let length = (WORM_REACH as usize * width.min(height))
+                    .saturating_sub(WORM_MAX_RADIUS.ceil() as usize + 1)
+                    .min(WORM_LENGTH);
+                let path = (0..length)
+                    .map(|step| {
+                        let t = step as f64 * WORM_TURN_SCALE;
+                        let angle = heading
+                            + perlin_2d(Vector2::new(t, lane), &self.worm_hasher)
+                                * std::f64::consts::PI;
+                        // mostly horizontal tunnels
+                        pos = pos.plus(V2::new(angle.cos(), angle.sin() * 0.6));
+                        let width_noise = perlin_2d(Vector2::new(lane, t), &self.worm_hasher);
+                        let radius = WORM_MIN_RADIUS
+                            + (WORM_MAX_RADIUS - WORM_MIN_RADIUS) * (width_noise + 1.0) / 2.0;
+                        (pos, radius)
+                    })
+                    .collect();
+                Some(path)
+            })
+            .collect()
+    }
+
+    // Which cells of a grid are dug out by worms, by `y * width + x`. Worms
+    // cross grid edges, so (like prefabs, see `stamp_prefabs`) each grid digs
+    // the parts of all the worms that can reach it, whichever grid they
+    // start in.
+    fn tunnels(&self, grid_index: GridIndex, width: usize, height: usize) -> Vec<bool> {
+        let mut tunnels = vec![false; width * height];
+        let base_pos = grid_index.to_pos(width, height);
+        for dy in -WORM_REACH..=WORM_REACH {
+            for dx in -WORM_REACH..=WORM_REACH {
+                let owner = GridIndex {
+                    grid_offset: grid_index.grid_offset.plus(V2i::new(dx, dy)),
+                };
+                for (center, radius) in self.worms(owner, width, height).into_iter().flatten() {
+                    let r = radius.ceil() as i32;
+                    let center_cell = center.round();
+                    for y in -r..=r {
+                        for x in -r..=r {
+                            let pos = center_cell.plus(V2i::new(x, y));
+                            let local = pos.minus(base_pos);
+                            if local.x < 0
+                                || local.y < 0
+                                || local.x >= width as i32
+                                || local.y >= height as i32
+                                || pos.to_v2().minus(center).magnitude_sqr() > radius * radius
+                            {
+                                continue;
+                            }
+                            tunnels[local.y as usize * width + local.x as usize] = true;
+                        }
+                    }
+                }
+            }
         }
+        tunnels
     }
 
-    pub fn generate_pristine_grid(
+    // Blends the biome of the column's grid with its nearest neighbor grid's,
+    // half and half at the edge between them, so the terrain is continuous.
+    fn column_biome(&self, x: i32, width: usize) -> ColumnBiome {
+        let grid_x = x.div_euclid(width as i32);
+        let local = (x.rem_euclid(width as i32) as f64 + 0.5) / width as f64;
+        let (other_x, weight) = if local < 0.5 {
+            (grid_x - 1, 0.5 + local)
+        } else {
+            (grid_x + 1, 1.5 - local)
+        };
+        let own = self.grid_biome(grid_x);
+        let other = self.grid_biome(other_x);
+        let blend = |f: fn(Biome) -> f64| weight * f(own) + (1.0 - weight) * f(other);
+
+        // surface materials are dithered close to the edge
+        let own_chance = ((weight - 0.5) * 8.0 + 0.5).min(1.0);
+        let dither = self.hasher.hash(&[x as isize]) as f64 / 256.0;
+        ColumnBiome {
+            biome: if dither < own_chance { own } else { other },
+            amplitude: blend(|b| b.props().amplitude),
+            cave_density: blend(|b| b.props().cave_density),
+            rock_shade: blend(|b| b.props().rock_shade),
+        }
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate_pristine_grid(
         &mut self,
         grid: &mut UniverseGrid<Cell>,
         grid_index: GridIndex,
@@ -54,33 +493,398 @@ impl Generator {
         height: usize,
     ) {
         let base_pos = grid_index.to_pos(width, height);
+        let tunnels = self.tunnels(grid_index, width, height);
+        // lakes are only above the ground level
+        let lake_levels = if base_pos.y < height as i32 {
+            self.lake_levels(base_pos.x, width)
+        } else {
+            vec![f64::NEG_INFINITY; width]
+        };
 
         for x in 0..width {
+            let column_x = base_pos.x + x as i32;
+            let column = self.column_biome(column_x, width);
+            // generate "mountains"
+            let surface = self.generated_point(V2i::new(column_x, 0)) * column.amplitude;
+            let water_table = self.water_table(column_x);
             for y in 0..height {
                 let pos = V2i::new(x as i32, y as i32).plus(base_pos);
                 let altitude = height as i32 - pos.y;
+                // liquids are generated at rest (static), so that a new
+                // grid doesn't start with lots of cells moving at once
+                let flooded = || self.is_flooded(pos, -altitude as f64, water_table);
+                if tunnels[y * width + x] {
+                    if flooded() {
+                        put_wall(grid, pos, Material::Water, 1.0);
+                    }
+                    continue;
+                }
                 let above_ground = altitude > 0;
-                if above_ground {
-                    // generate "mountains"
-                    let val = self.generated_point(V2i::new(pos.x, 0));
-
-                    if val * 100.0 > altitude as f64 {
-                        let cell = Self::wall_cell(pos, Color::hsv(30.0, 1.0, 0.5));
-                        grid.put(pos, Rc::new(RefCell::new(cell)));
+                let shade = if above_ground {
+                    if surface <= altitude as f64 {
+                        if altitude as f64 <= lake_levels[x] {
+                            put_wall(grid, pos, Material::Water, 1.0);
+                        }
+                        continue;
                     }
+                    1.0
                 } else {
                     // below ground
                     let val = self.generated_point(pos);
                     let depth = -altitude as f64;
-                    if val < 0.02 + 0.5 / (depth * 0.1) {
-                        let cell = Self::wall_cell(
-                            pos,
-                            Color::hsv(30.0, 1.0, (1.0 - val) * 0.5), // brown
-                        );
-                        grid.put(pos, Rc::new(RefCell::new(cell)));
+                    if val * column.cave_density >= 0.02 + 0.5 / (depth * 0.1) {
+                        if flooded() {
+                            put_wall(grid, pos, Material::Water, 1.0 - val);
+                        }
+                        continue;
                     }
+                    1.0 - val
+                };
+                let material = match column.biome.material_at(surface as i32 - altitude) {
+                    Material::Stone => self.ore_at(pos, -altitude as f64),
+                    material => Some(material),
+                };
+                match material {
+                    Some(ore) => put_wall(grid, pos, ore, shade),
+                    None => put_wall(grid, pos, Material::Stone, shade * column.rock_shade),
+                }
+            }
+        }
+
+        self.stamp_prefabs(grid, grid_index, width, height);
+    }
+}
+
+pub struct SkyGenerator;
+
+impl WorldGenerator for SkyGenerator {
+    fn generate_pristine_grid(
+        &mut self,
+        _grid: &mut UniverseGrid<Cell>,
+        _grid_index: GridIndex,
+        _width: usize,
+        _height: usize,
+    ) {
+    }
+}
+
+/// Solid ground up to a given height per column (a flat world if there are
+/// no heights at all)
+pub struct HeightmapGenerator {
+    heights: Vec<u32>,
+}
+
+impl HeightmapGenerator {
+    pub fn new(heights: Vec<u32>) -> Self {
+        Self { heights }
+    }
+
+    fn height_at(&self, x: i32) -> i32 {
+        usize::try_from(x)
+            .ok()
+            .and_then(|x| self.heights.get(x))
+            .map_or(0, |h| *h as i32)
+    }
+}
+
+impl WorldGenerator for HeightmapGenerator {
+    fn generate_pristine_grid(
+        &mut self,
+        grid: &mut UniverseGrid<Cell>,
+        grid_index: GridIndex,
+        width: usize,
+        height: usize,
+    ) {
+        let base_pos = grid_index.to_pos(width, height);
+
+        for x in 0..width {
+            let surface = height as i32 - self.height_at(base_pos.x + x as i32);
+            for y in 0..height {
+                let pos = V2i::new(x as i32, y as i32).plus(base_pos);
+                if pos.y >= surface {
+                    // a bit darker deeper down
+                    let shade = 1.0 - ((pos.y - surface) as f64 / 500.0).min(0.5);
+                    put_wall(grid, pos, Material::Stone, shade);
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(config: &GeneratorConfig, grid_index: GridIndex) -> UniverseGrid<Cell> {
+        let mut grid = UniverseGrid::new(grid_index, 8, 8);
+        config
+            .build(0)
+            .generate_pristine_grid(&mut grid, grid_index, 8, 8);
+        grid
+    }
+
+    fn is_solid(grid: &UniverseGrid<Cell>, pos: V2i) -> bool {
+        !grid.get(pos).value.is_empty()
+    }
+
+    #[test]
+    fn test_generator_names() {
+        for name in ["noise", "flat", "sky"] {
+            assert_eq!(GeneratorConfig::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(GeneratorConfig::from_name("Flat"), None);
+    }
+
+    #[test]
+    fn test_noise_generator_biomes() {
+        let generator = NoiseGenerator::new(0);
+        let biomes: Vec<Biome> = (-100..100).map(|x| generator.grid_biome(x)).collect();
+        for biome in Biome::ALL {
+            assert!(biomes.contains(&biome), "{biome:?} missing");
+        }
+
+        // mountain heights blend across grid edges
+        for grid_x in -20..20 {
+            let edge = grid_x * 128;
+            let left = generator.column_biome(edge - 1, 128);
+            let right = generator.column_biome(edge, 128);
+            assert!((left.amplitude - right.amplitude).abs() < 5.0);
+        }
+    }
+
+    #[test]
+    fn test_noise_generator_surface_material() {
+        let mut generator = NoiseGenerator::new(0);
+        let grid_x = (-100..100)
+            .find(|x| {
+                generator.grid_biome(*x - 1) == Biome::Desert
+                    && generator.grid_biome(*x) == Biome::Desert
+                    && generator.grid_biome(*x + 1) == Biome::Desert
+            })
+            .unwrap();
+        // the topmost solid cell of a column, in the grid above the ground
+        // level (mountains) or the one below it
+        let top = (0..2)
+            .flat_map(|grid_y| {
+                let grid_index = GridIndex {
+                    grid_offset: V2i::new(grid_x, grid_y),
+                };
+                let mut grid = UniverseGrid::new(grid_index, 128, 128);
+                generator.generate_pristine_grid(&mut grid, grid_index, 128, 128);
+                let base_pos = grid_index.to_pos(128, 128);
+                (0..128)
+                    .filter_map(|y| {
+                        let pos = base_pos.plus(V2i::new(64, y));
+                        grid.get(pos).value.first().map(|c| c.borrow().material)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .next();
+        assert_eq!(top, Some(Material::Sand));
+    }
+
+    // Ore cells in the grids `grid_y`, over a few columns of grids
+    fn count_ores(grid_y: i32) -> Vec<(Material, usize)> {
+        let mut generator = NoiseGenerator::new(0);
+        let mut counts: Vec<(Material, usize)> =
+            Material::ORES.iter().map(|ore| (*ore, 0)).collect();
+        for grid_x in 0..4 {
+            let grid_index = GridIndex {
+                grid_offset: V2i::new(grid_x, grid_y),
+            };
+            let mut grid = UniverseGrid::new(grid_index, 128, 128);
+            generator.generate_pristine_grid(&mut grid, grid_index, 128, 128);
+            let base_pos = grid_index.to_pos(128, 128);
+            for x in 0..128 {
+                for y in 0..128 {
+                    for cell in grid.get(base_pos.plus(V2i::new(x, y))).value {
+                        let material = cell.borrow().material;
+                        if let Some(count) = counts.iter_mut().find(|(m, _)| *m == material) {
+                            count.1 += 1;
+                        }
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn test_ores_by_depth() {
+        let shallow = count_ores(1);
+        let deep = count_ores(4);
+        // coal near the surface, gems only further down
+        assert!(shallow[0].1 > 0, "{shallow:?}");
+        assert_eq!(shallow[3].1, 0, "{shallow:?}");
+        // all of them deeper down
+        assert!(deep.iter().all(|(_, count)| *count > 0), "{deep:?}");
+        // rarer ores are rarer
+        assert!(deep[0].1 > deep[2].1, "{deep:?}");
+        assert!(deep[2].1 > deep[3].1, "{deep:?}");
+    }
+
+    #[test]
+    fn test_prefab_across_grid_edge() {
+        let generator = NoiseGenerator::new(0);
+        // a prefab reaching from its owner grid into the grid to the right
+        let (owner, prefab, corner) = (0..2)
+            .flat_map(|y| (-50..50).map(move |x| GridIndex::from_pos(V2i::new(x, y), 1, 1)))
+            .find_map(|owner| {
+                let (prefab, corner) = generator.prefab_placement(owner, 128, 128)?;
+                let right_edge = owner.to_pos(128, 128).x + 128;
+                (corner.x + prefab.width as i32 > right_edge).then_some((owner, prefab, corner))
+            })
+            .unwrap();
+
+        // each grid generated on its own, by its own generator
+        let grids: Vec<UniverseGrid<Cell>> = [0, 1]
+            .iter()
+            .map(|dx| {
+                let grid_index = GridIndex {
+                    grid_offset: owner.grid_offset.plus(V2i::new(*dx, 0)),
+                };
+                let mut grid = UniverseGrid::new(grid_index, 128, 128);
+                NoiseGenerator::new(0).generate_pristine_grid(&mut grid, grid_index, 128, 128);
+                grid
+            })
+            .collect();
+
+        let mut stamped = [0, 0];
+        for y in 0..prefab.height as i32 {
+            for x in 0..prefab.width as i32 {
+                let pos = corner.plus(V2i::new(x, y));
+                let i = grids.iter().position(|g| g.is_in_bounds(pos)).unwrap();
+                let cells = grids[i].get(pos).value;
+                match prefab.cell(V2i::new(x, y)) {
+                    PrefabCell::Keep => continue,
+                    PrefabCell::Air => assert!(cells.is_empty()),
+                    PrefabCell::Wall(material, _) => {
+                        assert_eq!(cells.len(), 1);
+                        assert_eq!(cells[0].borrow().material, material);
+                    }
+                }
+                stamped[i] += 1;
+            }
+        }
+        assert!(stamped[0] > 0 && stamped[1] > 0, "{stamped:?}");
+    }
+
+    #[test]
+    fn test_worm_tunnels_across_grid_edges() {
+        let generator = NoiseGenerator::new(0);
+        let owner = GridIndex {
+            grid_offset: V2i::new(0, 2),
+        };
+        let worms = generator.worms(owner, 128, 128);
+        assert!(!worms.is_empty());
+
+        // every grid a worm crawls through digs its part of the tunnel
+        let mut crossed = 0;
+        let mut grids = std::collections::HashMap::new();
+        for (center, _) in worms.into_iter().flatten() {
+            let pos = center.round();
+            let grid_index = GridIndex::from_pos(pos, 128, 128);
+            if grid_index != owner {
+                crossed += 1;
+            }
+            let local = pos.minus(grid_index.to_pos(128, 128));
+            let tunnels = grids
+                .entry(grid_index)
+                .or_insert_with(|| generator.tunnels(grid_index, 128, 128));
+            assert!(tunnels[local.y as usize * 128 + local.x as usize]);
+        }
+        assert!(crossed > 0);
+    }
+
+    #[test]
+    fn test_generated_liquids_at_rest() {
+        let mut generator = NoiseGenerator::new(0);
+        // lakes above the ground level, aquifers deeper down
+        for grid_y in [0, 2] {
+            let mut water = 0;
+            for grid_x in -4..4 {
+                let grid_index = GridIndex {
+                    grid_offset: V2i::new(grid_x, grid_y),
+                };
+                let mut grid = UniverseGrid::new(grid_index, 128, 128);
+                generator.generate_pristine_grid(&mut grid, grid_index, 128, 128);
+                let base_pos = grid_index.to_pos(128, 128);
+                for pos in (0..128).flat_map(|x| (0..128).map(move |y| V2i::new(x, y))) {
+                    for cell in grid.get(base_pos.plus(pos)).value {
+                        let cell = cell.borrow();
+                        if cell.material.is_liquid() {
+                            assert_eq!(cell.state, CellState::Sleeping);
+                            water += 1;
+                        }
+                    }
+                }
+            }
+            assert!(water > 0, "no water in grids at y = {grid_y}");
+        }
+    }
+
+    #[test]
+    fn test_flat_generator() {
+        let above = GridIndex {
+            grid_offset: V2i::new(3, 0),
+        };
+        let grid = generate(&GeneratorConfig::Flat, above);
+        assert!(!is_solid(&grid, V2i::new(25, 7)));
+
+        let below = GridIndex {
+            grid_offset: V2i::new(-3, 1),
+        };
+        let grid = generate(&GeneratorConfig::Flat, below);
+        assert!(is_solid(&grid, V2i::new(-20, 8)));
+        assert!(is_solid(&grid, V2i::new(-17, 15)));
+    }
+
+    #[test]
+    fn test_sky_generator() {
+        let grid_index = GridIndex {
+            grid_offset: V2i::new(0, 5),
+        };
+        let grid = generate(&GeneratorConfig::Sky, grid_index);
+        assert!(!is_solid(&grid, V2i::new(3, 43)));
+    }
+
+    #[test]
+    fn test_heightmap_generator() {
+        let config = GeneratorConfig::Heightmap {
+            heights: vec![0, 3, 8],
+        };
+        let grid_index = GridIndex {
+            grid_offset: V2i::new(0, 0),
+        };
+        let grid = generate(&config, grid_index);
+        // ground starts at y = 8 - height
+        assert!(!is_solid(&grid, V2i::new(0, 7)));
+        assert!(!is_solid(&grid, V2i::new(1, 4)));
+        assert!(is_solid(&grid, V2i::new(1, 5)));
+        assert!(is_solid(&grid, V2i::new(2, 0)));
+        // flat beyond the heightmap
+        assert!(!is_solid(&grid, V2i::new(3, 7)));
+    }
+
+    #[test]
+    fn test_heightmap_from_image() {
+        // 3x1 grayscale: black, gray, white
+        let mut img = image::GrayImage::new(3, 1);
+        img.put_pixel(1, 0, image::Luma([128]));
+        img.put_pixel(2, 0, image::Luma([255]));
+        let mut bytes = Vec::new();
+        img.write_to(
+            &mut std::io::Cursor::new(&mut bytes),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+
+        let config = GeneratorConfig::heightmap_from_image(&bytes, 100).unwrap();
+        assert_eq!(
+            config,
+            GeneratorConfig::Heightmap {
+                heights: vec![0, 50, 100]
+            }
+        );
+    }
+}
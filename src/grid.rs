@@ -12,8 +12,12 @@
 // - **Versioning**: The `GridCell` uses a `version` to track changes. This allows for
 //   efficient clearing of cell data without reallocating memory.
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
-use wasm_bindgen::JsValue;
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    io::{Read, Write},
+    rc::Rc,
+};
 
 pub type GridCellRef<T> = Rc<RefCell<T>>;
 
@@ -47,7 +51,7 @@ impl<T: Debug> GridCell<T> {
         }
     }
 
-    pub fn get(&self, version: usize) -> GetResult<T> {
+    pub fn get(&self, version: usize) -> GetResult<'_, T> {
         if version == self.version {
             GetResult {
                 value: &self.value,
@@ -112,7 +116,7 @@ fn grid_index(x: usize, y: usize, height: usize) -> usize {
 impl<T: Debug> Grid<T> {
     pub fn new(width: usize, height: usize) -> Grid<T> {
         let mut grid: Vec<GridCell<T>> =
-            Vec::with_capacity(((width / FACTOR + 2) * (height / FACTOR + 2)) as usize);
+            Vec::with_capacity((width / FACTOR + 2) * (height / FACTOR + 2));
         for _ in 0..((width / FACTOR + 2) * (height / FACTOR + 2)) {
             grid.push(GridCell::new());
         }
@@ -140,7 +144,7 @@ impl<T: Debug> Grid<T> {
         self.version += 1;
     } */
 
-    pub fn get(&self, x: usize, y: usize) -> GetResult<T> {
+    pub fn get(&self, x: usize, y: usize) -> GetResult<'_, T> {
         assert!(x < self.width);
         assert!(y < self.height);
         self.grid[grid_index(x + 1, y + 1, self.height)].get(self.version)
@@ -160,12 +164,7 @@ impl<T: Debug> Grid<T> {
 }
 
 impl<T: Debug + Clone> Grid<T> {
-    /// Serialize the grid to bytes
-    /// This serializes the grid dimensions and all items with their positions
-    pub fn to_bytes(&self) -> Result<JsValue, serde_wasm_bindgen::Error>
-    where
-        T: serde::Serialize,
-    {
+    fn to_serial_data(&self) -> GridSerialData<T> {
         let mut items = Vec::new();
 
         for x in 0..self.width {
@@ -178,23 +177,15 @@ impl<T: Debug + Clone> Grid<T> {
             }
         }
 
-        let grid_data = GridSerialData {
+        GridSerialData {
             width: self.width,
             height: self.height,
             version: self.version,
             items,
-        };
-
-        serde_wasm_bindgen::to_value(&grid_data)
+        }
     }
 
-    /// Deserialize the grid from bytes
-    pub fn from_bytes(bytes: JsValue) -> Result<Self, serde_wasm_bindgen::Error>
-    where
-        T: serde::de::DeserializeOwned,
-    {
-        let grid_data: GridSerialData<T> = serde_wasm_bindgen::from_value(bytes)?;
-
+    fn from_serial_data(grid_data: GridSerialData<T>) -> Self {
         let mut grid = Grid::new(grid_data.width, grid_data.height);
         grid.version = grid_data.version;
 
@@ -204,7 +195,39 @@ impl<T: Debug + Clone> Grid<T> {
             grid.put(x, y, item_ref);
         }
 
-        Ok(grid)
+        grid
+    }
+
+    /// Serialize the grid dimensions and all items with their positions
+    pub fn write_to(&self, writer: impl Write) -> bincode::Result<()>
+    where
+        T: Serialize,
+    {
+        bincode::serialize_into(writer, &self.to_serial_data())
+    }
+
+    /// Deserialize a grid written by `write_to`
+    pub fn read_from(reader: impl Read) -> bincode::Result<Self>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        Ok(Self::from_serial_data(bincode::deserialize_from(reader)?))
+    }
+
+    pub fn to_vec(&self) -> bincode::Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        let mut bytes = Vec::new();
+        self.write_to(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn from_slice(bytes: &[u8]) -> bincode::Result<Self>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        Self::read_from(bytes)
     }
 }
 
@@ -225,8 +248,8 @@ mod tests {
         grid.put(0, 0, a.clone());
         let res = grid.get(0, 0);
         assert_eq!(res.neighbors.len(), 1);
-        assert_eq!(res.value, &[a.clone()]);
-        assert_eq!(res.neighbors, &[a.clone()]);
+        assert_eq!(res.value, std::slice::from_ref(&a));
+        assert_eq!(res.neighbors, std::slice::from_ref(&a));
 
         grid.remove(0, 0, &a);
         let res = grid.get(0, 0);
@@ -245,14 +268,14 @@ mod tests {
         let res = grid.get(0, 0);
 
         assert_eq!(res.neighbors.len(), 2);
-        assert_eq!(res.value, &[a.clone()]);
+        assert_eq!(res.value, std::slice::from_ref(&a));
         assert_eq!(res.neighbors, &[a.clone(), b.clone()]);
 
         grid.remove(0, 0, &a);
         let res = grid.get(0, 0);
         assert_eq!(res.neighbors.len(), 1);
         assert_eq!(res.value, &[]);
-        assert_eq!(res.neighbors, &[b.clone()]);
+        assert_eq!(res.neighbors, std::slice::from_ref(&b));
     }
 
     #[test]
@@ -266,14 +289,14 @@ mod tests {
         {
             let res = grid.get(0, 0);
             assert_eq!(res.neighbors.len(), 1);
-            assert_eq!(res.value, &[a.clone()]);
-            assert_eq!(res.neighbors, &[a.clone()]);
+            assert_eq!(res.value, std::slice::from_ref(&a));
+            assert_eq!(res.neighbors, std::slice::from_ref(&a));
         }
         {
             let res = grid.get(4, 0);
             assert_eq!(res.neighbors.len(), 1);
-            assert_eq!(res.value, &[b.clone()]);
-            assert_eq!(res.neighbors, &[b.clone()]);
+            assert_eq!(res.value, std::slice::from_ref(&b));
+            assert_eq!(res.neighbors, std::slice::from_ref(&b));
         }
     }
 
@@ -289,11 +312,11 @@ mod tests {
         grid.put(2, 2, c.clone());
 
         // Serialize the grid
-        let bytes = grid.to_bytes();
-        assert!(!bytes.is_err());
+        let bytes = grid.to_vec();
+        assert!(bytes.is_ok());
 
         // Deserialize the grid
-        let restored_grid: Grid<char> = Grid::from_bytes(bytes.unwrap()).unwrap();
+        let restored_grid: Grid<char> = Grid::from_slice(&bytes.unwrap()).unwrap();
 
         // Verify dimensions and version
         assert_eq!(restored_grid.width, 3);
@@ -322,10 +345,10 @@ mod tests {
     fn test_grid_empty_serialization() {
         let grid: Grid<i32> = Grid::new(2, 2);
 
-        let bytes = grid.to_bytes();
-        assert!(!bytes.is_err());
+        let bytes = grid.to_vec();
+        assert!(bytes.is_ok());
 
-        let restored_grid: Grid<i32> = Grid::from_bytes(bytes.unwrap()).unwrap();
+        let restored_grid: Grid<i32> = Grid::from_slice(&bytes.unwrap()).unwrap();
         assert_eq!(restored_grid.width, 2);
         assert_eq!(restored_grid.height, 2);
 
@@ -338,6 +361,32 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_grid_write_read() {
+        let mut grid: Grid<char> = Grid::new(4, 4);
+        grid.put(3, 2, Rc::new(RefCell::new('x')));
+
+        let mut buf = Vec::new();
+        grid.write_to(&mut buf).unwrap();
+        assert_eq!(buf, grid.to_vec().unwrap());
+
+        let restored_grid: Grid<char> = Grid::read_from(buf.as_slice()).unwrap();
+        let res = restored_grid.get(3, 2);
+        assert_eq!(res.value.len(), 1);
+        assert_eq!(*res.value[0].borrow(), 'x');
+        assert_eq!(res.neighbors.len(), 1);
+        assert_eq!(restored_grid.get(2, 2).neighbors.len(), 1);
+    }
+
+    #[test]
+    fn test_grid_from_slice_truncated() {
+        let mut grid: Grid<char> = Grid::new(2, 2);
+        grid.put(1, 1, Rc::new(RefCell::new('y')));
+        let bytes = grid.to_vec().unwrap();
+
+        assert!(Grid::<char>::from_slice(&bytes[..bytes.len() - 1]).is_err());
+    }
+
     /*
     #[test]
     fn test_grid_clear() {
@@ -1,3 +1,4 @@
+use crate::material::Material;
 use crate::v2::V2;
 
 #[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
@@ -6,31 +7,40 @@ pub struct Inertia {
     pub force: V2,
     pub pos: V2,
     pub mass: i32,
-    pub elasticity: f64, // 0..1
     pub collision_stats: usize,
 }
 
-fn fixup_mass(mass: i32) -> i32 {
+fn fixup_mass(mass: i32, immovable: bool) -> i32 {
     if mass < 0 {
         panic!("Mass cannot be negative: {mass}");
     }
-    if mass == 0 {
+    if immovable {
         return 10_000_000; // large mass
     }
-    return mass;
+    mass
 }
 
 fn inverse_mass(mass: f64) -> f64 {
     if mass.abs() < 0.000001 {
         return 0.0;
     }
-    return 1.0 / (mass as f64);
+    1.0 / mass
 }
 
 impl Inertia {
-    pub fn collide(inertia1: &Inertia, inertia2: &Inertia) -> (Inertia, Inertia) {
-        let m1 = fixup_mass(inertia1.mass) as f64;
-        let m2 = fixup_mass(inertia2.mass) as f64;
+    /// Resolves a collision between two cells. An immovable one (a cell at
+    /// rest) acts as if its mass was huge, and keeps its position and
+    /// velocity.
+    pub fn collide(
+        inertia1: &Inertia,
+        material1: Material,
+        immovable1: bool,
+        inertia2: &Inertia,
+        material2: Material,
+        immovable2: bool,
+    ) -> (Inertia, Inertia) {
+        let m1 = fixup_mass(inertia1.mass, immovable1) as f64;
+        let m2 = fixup_mass(inertia2.mass, immovable2) as f64;
 
         let v1 = inertia1.velocity;
         let v2 = inertia2.velocity;
@@ -45,11 +55,37 @@ impl Inertia {
             // objects are moving away from each other
             return (*inertia1, *inertia2);
         }
-        let e = inertia1.elasticity.min(inertia2.elasticity);
-        let j = (m1 * m2) / (m1 + m2) * (1.0 + e) * (v_rel);
+        let e = material1.elasticity().min(material2.elasticity());
+        let reduced_mass = (m1 * m2) / (m1 + m2);
+        let j = reduced_mass * (1.0 + e) * (v_rel);
+
+        // Friction: an impulse against the sliding (tangential) velocity. It
+        // stops the sliding if that takes no more than static friction times
+        // the impulse stopping the cells' approach, so a cell rests on a
+        // slope up to atan(static friction) steep; otherwise the cells keep
+        // sliding, slowed by kinetic friction.
+        let sliding = v2.minus(v1).minus(normal.cmul(v_rel));
+        let sliding_speed = sliding.magnitude();
+        let friction_impulse = if sliding_speed > 0.000001 {
+            let normal_impulse = reduced_mass * -v_rel;
+            let stop_impulse = reduced_mass * sliding_speed;
+            let static_friction = material1.static_friction().min(material2.static_friction());
+            let kinetic_friction = material1.friction().min(material2.friction());
+            let jt = if stop_impulse <= static_friction * normal_impulse {
+                stop_impulse
+            } else {
+                kinetic_friction * normal_impulse
+            };
+            sliding.cmul(jt / sliding_speed)
+        } else {
+            V2::zero()
+        };
 
-        let u1 = normal.cmul(j / m1).plus(v1);
-        let u2 = normal.cmul(-j / m2).plus(v2);
+        let u1 = normal.cmul(j / m1).plus(v1).plus(friction_impulse.cdiv(m1));
+        let u2 = normal
+            .cmul(-j / m2)
+            .plus(v2)
+            .minus(friction_impulse.cdiv(m2));
 
         let im1 = inverse_mass(m1);
         let im2 = inverse_mass(m2);
@@ -62,15 +98,15 @@ impl Inertia {
             V2::zero()
         };
 
-        let uf1 = if inertia1.mass == 0 { v1 } else { u1 };
-        let uf2 = if inertia2.mass == 0 { v2 } else { u2 };
+        let uf1 = if immovable1 { v1 } else { u1 };
+        let uf2 = if immovable2 { v2 } else { u2 };
 
-        let p1 = if inertia1.mass == 0 {
+        let p1 = if immovable1 {
             x1
         } else {
             x1.minus(pos_correct.cmul(im1))
         };
-        let p2 = if inertia2.mass == 0 {
+        let p2 = if immovable2 {
             x2
         } else {
             x2.plus(pos_correct.cmul(im2))
@@ -90,11 +126,6 @@ impl Inertia {
     }
 
     pub fn is_collision(inertia1: &Inertia, inertia2: &Inertia) -> bool {
-        // collision between infinite masses?!
-        if (inertia1.mass == 0) && (inertia2.mass == 0) {
-            return false;
-        }
-
         let normal = inertia1.pos.minus(inertia2.pos);
         let radius = 1.0; // they're actually boxes but ok
         if normal.magnitude_sqr() > radius * radius {
@@ -117,6 +148,65 @@ impl Inertia {
             return false;
         }
 
-        return true;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inertia(pos: V2, velocity: V2, mass: i32) -> Inertia {
+        Inertia {
+            velocity,
+            force: V2::zero(),
+            pos,
+            mass,
+            collision_stats: 0,
+        }
+    }
+
+    // The sliding speed of a sand cell falling straight down onto a static
+    // cell below it, `angle` radians off to the side
+    fn sliding_after_landing(angle: f64) -> f64 {
+        let floor = inertia(V2::zero(), V2::zero(), Material::Stone.density());
+        let normal = V2::new(angle.sin(), -angle.cos());
+        let falling = inertia(normal.cmul(0.99), V2::new(0.0, 1.0), 1);
+        let (_, landed) = Inertia::collide(
+            &floor,
+            Material::Stone,
+            true,
+            &falling,
+            Material::Sand,
+            false,
+        );
+        let v = landed.velocity;
+        v.minus(normal.cmul(v.dot(normal))).magnitude()
+    }
+
+    #[test]
+    fn test_angle_of_repose() {
+        let repose = Material::Sand
+            .static_friction()
+            .min(Material::Stone.static_friction())
+            .atan();
+        assert!(sliding_after_landing(repose - 0.05) < 0.000001);
+        assert!(sliding_after_landing(repose + 0.05) > 0.01);
+    }
+
+    #[test]
+    fn test_kinetic_friction_slows_sliding() {
+        let floor = inertia(V2::new(0.0, 1.0), V2::zero(), Material::Stone.density());
+        // sliding fast along the floor while pressing into it
+        let cell = inertia(V2::zero(), V2::new(2.0, 0.1), 1);
+        let (moved, _) =
+            Inertia::collide(&cell, Material::Sand, false, &floor, Material::Stone, true);
+        let friction = Material::Sand.friction().min(Material::Stone.friction());
+        assert!((moved.velocity.x - (2.0 - friction * 0.1)).abs() < 0.001);
+
+        // no friction on water
+        let (moved, _) =
+            Inertia::collide(&cell, Material::Water, false, &floor, Material::Stone, true);
+        assert!((moved.velocity.x - 2.0).abs() < 0.001);
     }
 }
@@ -1,11 +1,19 @@
 use std::collections::HashSet;
+use std::io::{Read, Write};
 use std::sync::LazyLock;
 mod inertia;
 
 mod assets;
+mod biome;
 mod color;
 mod grid;
+mod material;
 mod multigrid;
+mod prefab;
+mod preview;
+mod region;
+mod rng;
+mod store;
 mod universe;
 mod utils;
 mod v2;
@@ -14,15 +22,26 @@ use color::Color;
 mod generator;
 mod log;
 
+// Rust transliteration of tla/GridSystem.tla, used to check `MultiGrid`
+#[cfg(test)]
+#[path = "../tla/grid_system.rs"]
+mod grid_system;
+
 use noise::Vector2;
 use noise::{core::perlin::perlin_2d, permutationtable::PermutationTable};
 
 use inertia::Inertia;
 use log::log;
+use material::Material;
 use multigrid::{CellIndex, GridIndex};
-use universe::{Cell, Stats, Universe};
+use universe::{Cell, CellState, Stats, Universe};
 
 use v2::{V2i, V2};
+
+pub use generator::GeneratorConfig;
+pub use preview::{ChunkStats, Preview};
+pub use region::ColorMapping;
+pub use store::{ArchiveStore, ChunkStore, DirStore, MemStore};
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -33,12 +52,20 @@ pub struct Game {
     universe: Universe,
     keys: HashSet<String>,
     shoot_color: Color,
+    shoot_material: Material,
     hasher: PermutationTable,
 }
 
 static GRID_SIZE: usize = 128;
 
-static BUILD_TIME: LazyLock<chrono::DateTime<chrono::Utc>> = LazyLock::new(|| chrono::Utc::now());
+const BOMB_COLOR: u32 = 0x000000;
+
+// The background clouds use a different permutation than the terrain
+fn background_hasher(seed: u32) -> PermutationTable {
+    PermutationTable::new(seed.wrapping_add(1))
+}
+
+static BUILD_TIME: LazyLock<chrono::DateTime<chrono::Utc>> = LazyLock::new(chrono::Utc::now);
 
 macro_rules! cargo_build_time {
     () => {
@@ -49,16 +76,65 @@ macro_rules! cargo_build_time {
 #[wasm_bindgen]
 impl Game {
     pub fn new(width: usize, height: usize) -> Self {
+        Self::new_with_seed(width, height, 0)
+    }
+
+    /// A game whose world (terrain, background, ...) derives from `seed`, so
+    /// the same seed always gives the same world
+    pub fn new_with_seed(width: usize, height: usize, seed: u32) -> Self {
         utils::set_panic_hook();
 
         Self {
             width,
             height,
-            pixels: vec![0xFFFFFF; (width * height) as usize],
-            universe: Universe::new(GRID_SIZE, GRID_SIZE),
+            pixels: vec![0xFFFFFF; width * height],
+            universe: Universe::new_with_seed(GRID_SIZE, GRID_SIZE, seed),
             keys: HashSet::new(),
             shoot_color: Color::hsv(90.0, 1.0, 1.0),
-            hasher: PermutationTable::new(1),
+            shoot_material: Material::Sand,
+            hasher: background_hasher(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.universe.seed()
+    }
+
+    /// The y where the generators' ground starts (one grid height down)
+    pub fn ground_level(&self) -> i32 {
+        GRID_SIZE as i32
+    }
+
+    /// The name of the world generator in use, see `use_generator`
+    pub fn generator_name(&self) -> String {
+        self.universe.generator_config().name().to_string()
+    }
+
+    /// Switches to the "noise", "flat" or "sky" world generator, see
+    /// `set_generator`. Returns false for unknown names.
+    pub fn use_generator(&mut self, name: &str) -> bool {
+        match GeneratorConfig::from_name(name) {
+            Some(config) => {
+                self.set_generator(config);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Switches to a generator with ground shaped like the image's columns,
+    /// see `GeneratorConfig::heightmap_from_image`
+    pub fn use_heightmap(&mut self, png: JsValue, max_height: u32) -> bool {
+        let bytes = js_sys::Uint8Array::new(&png).to_vec();
+        match GeneratorConfig::heightmap_from_image(&bytes, max_height) {
+            Ok(config) => {
+                self.set_generator(config);
+                true
+            }
+            Err(err) => {
+                log!("Failed to read heightmap: {}", err);
+                false
+            }
         }
     }
 
@@ -88,33 +164,89 @@ impl Game {
         self.universe.get_loaded_grids()
     }
 
-    pub fn get_droppable_grids(&self) -> Vec<GridIndex> {
-        self.universe.get_droppable_grids()
+    pub fn get_dirty_grids(&self) -> Vec<GridIndex> {
+        self.universe.get_dirty_grids()
     }
 
-    pub fn load_grid(&mut self, grid_index: &GridIndex, bytes: JsValue) {
-        if let Err(err) = self.universe.load_from_storage(*grid_index, bytes) {
-            log!("Failed to load grid {grid_index:?}: {}", err);
+    /// Keeps the grids in the frontend's persistent storage: the game only
+    /// loads the grids passed in with `store_grid` (or generates the ones
+    /// passed to `no_stored_grid`), see `MemStore::staging`. Should be called
+    /// before the world header is restored.
+    pub fn use_staging_store(&mut self) {
+        if let Err(err) = self.set_store(Box::new(MemStore::staging())) {
+            log!("Failed to switch stores: {}", err);
         }
     }
 
-    pub fn generate_grid(&mut self, grid_index: &GridIndex) {
-        self.universe.cells.ensure_grid(*grid_index);
+    /// Puts a grid read from the frontend's persistent storage into the
+    /// store, so it is loaded (instead of generated) when needed
+    pub fn store_grid(&mut self, grid_index: &GridIndex, bytes: JsValue) {
+        let bytes = js_sys::Uint8Array::new(&bytes).to_vec();
+        if let Err(err) = self.universe.store().save_grid(*grid_index, &bytes) {
+            log!("Failed to store grid {grid_index:?}: {}", err);
+        }
     }
 
-    pub fn save_grid(&mut self, grid_index: &GridIndex) -> JsValue {
-        if let Some(bytes) = self.universe.save_grid(*grid_index) {
-            bytes
-        } else {
-            JsValue::null()
+    /// Tells the store that the frontend's persistent storage has no copy of
+    /// a grid, so it can be generated
+    pub fn no_stored_grid(&mut self, grid_index: &GridIndex) {
+        self.universe.store().mark_absent(*grid_index);
+    }
+
+    /// The stored bytes of a grid (as a `Uint8Array`), or null. The store
+    /// forgets them, the frontend's persistent storage has them from now on
+    /// (see `store_grid`).
+    pub fn stored_grid(&mut self, grid_index: &GridIndex) -> JsValue {
+        match self.universe.store().take_grid(*grid_index) {
+            Ok(Some(bytes)) => js_sys::Uint8Array::from(bytes.as_slice()).into(),
+            Ok(None) => JsValue::null(),
+            Err(err) => {
+                log!("Failed to read stored grid {grid_index:?}: {}", err);
+                JsValue::null()
+            }
         }
     }
 
-    pub fn drop_grid(&mut self, grid_index: &GridIndex) {
-        self.universe.drop_grid(*grid_index)
+    /// Restores the world header (player, seed, ...) read from the frontend's
+    /// persistent storage. Should be called before the first `stream`.
+    pub fn store_header(&mut self, bytes: JsValue) {
+        let bytes = js_sys::Uint8Array::new(&bytes).to_vec();
+        if let Err(err) = self.universe.read_header(bytes.as_slice()) {
+            log!("Failed to read world header: {}", err);
+            return;
+        }
+        self.hasher = background_hasher(self.universe.seed());
+        if let Err(err) = self.universe.store().save_meta(&bytes) {
+            log!("Failed to store world header: {}", err);
+        }
     }
 
-    pub fn render(&mut self) -> () {
+    /// The stored world header (as a `Uint8Array`), or null
+    pub fn stored_header(&mut self) -> JsValue {
+        match self.universe.store().load_meta() {
+            Ok(Some(bytes)) => js_sys::Uint8Array::from(bytes.as_slice()).into(),
+            Ok(None) => JsValue::null(),
+            Err(err) => {
+                log!("Failed to read world header: {}", err);
+                JsValue::null()
+            }
+        }
+    }
+
+    /// Loads, stores and drops grids around the player, see
+    /// `Universe::stream`. Returns the grids that were stored (only the
+    /// dirty ones).
+    pub fn stream(&mut self) -> Vec<GridIndex> {
+        match self.universe.stream() {
+            Ok(saved) => saved,
+            Err(err) => {
+                log!("Failed to stream grids: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    pub fn render(&mut self) {
         let is_dig_mode = self.is_dig_mode();
         self.pixels.fill(0xFFFFFF);
 
@@ -135,6 +267,12 @@ impl Game {
         for res in get_res.iter() {
             self.render_cell(res, base_pos);
         }
+        for bomb in self.universe.bombs() {
+            let pixel_pos = bomb.inertia.pos.round().minus(base_pos);
+            if self.is_in_bounds(pixel_pos.x, pixel_pos.y) {
+                self.pixels[(pixel_pos.y * w + pixel_pos.x) as usize] = BOMB_COLOR;
+            }
+        }
         self.universe.player.render(
             &mut self.pixels,
             self.universe.player.inertia.pos.round().minus(base_pos),
@@ -149,31 +287,36 @@ impl Game {
         let pixel_pos = pos.minus(base_pos);
         let w = self.width as i32;
         let pixel_idx = (pixel_pos.y * w + pixel_pos.x) as usize;
+        self.pixels[pixel_idx] = self.cell_pixel(res, 2 * self.height as i32);
+    }
+
+    // The color of a position with the given cells, over a background whose
+    // horizon is at `horizon`
+    fn cell_pixel(&self, res: &(V2i, Vec<grid::GridCellRef<Cell>>), horizon: i32) -> u32 {
         if res.1.is_empty() {
-            self.pixels[pixel_idx] = self.render_background(pos);
-            return;
+            return self.render_background(res.0, horizon);
         }
-        self.pixels[pixel_idx] = 0;
+        let mut pixel: u32 = 0;
         for cell_ref in res.1.iter() {
             let cell = cell_ref.borrow();
-            let cell_color = if cell.inertia.collision_stats > 0 && cell.inertia.mass > 0 {
+            let cell_color = if cell.inertia.collision_stats > 0 && cell.is_moving() {
                 0xFF0000
             } else {
                 cell.color.to_u32()
             };
-            self.pixels[pixel_idx] = self.pixels[pixel_idx].saturating_add(cell_color);
+            pixel = pixel.saturating_add(cell_color);
         }
+        pixel
     }
 
-    fn render_background(&self, pos: V2i) -> u32 {
+    fn render_background(&self, pos: V2i, horizon: i32) -> u32 {
         let hasher = &self.hasher;
-        let depth = pos.y - (self.height as i32);
-        if depth >= self.height as i32 {
+        if pos.y >= horizon {
             // underground - deeper is darker
-            let value = (255.0 / ((depth + 2) as f64).powf(0.5)) as u32;
+            let value = (255.0 / ((pos.y - horizon / 2 + 2) as f64).powf(0.5)) as u32;
             value + (value << 8) + (value << 16)
         } else {
-            let altitude = -depth as f64 + self.height as f64;
+            let altitude = (horizon - pos.y) as f64;
             // generate clouds
             let posv = pos.to_v2().plus(V2::new(0.5, 0.7)).cmul(0.01);
             let noise2 = perlin_2d(Vector2::new(posv.y * 10.0, posv.x * 10.0), hasher);
@@ -187,7 +330,13 @@ impl Game {
     }
 
     pub fn key_down(&mut self, key: String) {
-        self.keys.insert(key.to_ascii_lowercase());
+        let key = key.to_ascii_lowercase();
+        if key == "m" && !self.keys.contains(&key) {
+            // cycle through the materials we can shoot
+            self.shoot_material = self.shoot_material.next();
+            self.shoot_color = self.shoot_material.color(1.0);
+        }
+        self.keys.insert(key);
     }
 
     pub fn key_up(&mut self, key: String) {
@@ -217,8 +366,7 @@ impl Game {
             let key = raw_key.chars().nth(0).unwrap();
             match key {
                 c @ '0'..='9' => {
-                    self.shoot_color =
-                        Color::hsv((c as u8 - '0' as u8) as f64 / 10.0 * 360.0, 1.0, 1.0);
+                    self.shoot_color = Color::hsv((c as u8 - b'0') as f64 / 10.0 * 360.0, 1.0, 1.0);
                 }
                 'a' => {
                     self.universe.player.move_left();
@@ -251,12 +399,21 @@ impl Game {
                             velocity: V2::new(1.0 * (self.universe.player.direction as f64), -1.0),
                             force: V2::zero(),
                             pos: self.universe.player.mouth_pos(),
-                            mass: 1,
-                            elasticity: universe::ELASTICITY,
+                            mass: self.shoot_material.density(),
                             collision_stats: 0,
                         },
+                        material: self.shoot_material,
+                        state: CellState::Moving,
                     });
                 }
+                // one bomb at a time
+                'b' if self.universe.bombs().is_empty() => {
+                    self.universe.player.next_frame();
+                    self.universe.throw_bomb(
+                        self.universe.player.mouth_pos(),
+                        V2::new(1.0 * (self.universe.player.direction as f64), -1.0),
+                    );
+                }
                 _ => (),
             }
         }
@@ -327,4 +484,195 @@ impl Game {
     pub fn pixels_vec(&self) -> &Vec<u32> {
         &self.pixels
     }
+
+    pub fn write_universe(&self, writer: impl Write) -> bincode::Result<()> {
+        self.universe.write_to(writer)
+    }
+
+    pub fn read_universe(&mut self, reader: impl Read) -> bincode::Result<()> {
+        self.universe = Universe::read_from(reader)?;
+        Ok(())
+    }
+
+    /// Makes the game load and save its world through `store`, restoring
+    /// the player etc. if the store has a saved world
+    pub fn set_store(
+        &mut self,
+        store: Box<dyn ChunkStore>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.universe.set_store(store)?;
+        // the stored world may have a different seed
+        self.hasher = background_hasher(self.universe.seed());
+        Ok(())
+    }
+
+    /// Sets the generator for new worlds. Should be called before any grid
+    /// is loaded; a saved world (see `set_store`) keeps its own generator.
+    pub fn set_generator(&mut self, config: GeneratorConfig) {
+        self.universe.set_generator(config);
+    }
+
+    /// Draws a PNG into the world, see `UniverseCells::import_image`.
+    /// Returns the number of cells created.
+    pub fn import_image(
+        &mut self,
+        png: &[u8],
+        x: i32,
+        y: i32,
+        mapping: &ColorMapping,
+    ) -> Result<usize, image::ImageError> {
+        self.universe
+            .cells
+            .import_image(png, V2i::new(x, y), mapping)
+    }
+
+    /// Renders the world rectangle with its top left corner at `x, y` to a
+    /// PNG, one pixel per cell (without the player), over a background with
+    /// its horizon at `horizon` (see `ground_level`). Grids that aren't
+    /// loaded are loaded from the store or generated, one at a time, and
+    /// dropped again when done.
+    pub fn export_png(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        horizon: i32,
+    ) -> Result<Vec<u8>, image::ImageError> {
+        let mut img = image::RgbImage::new(width, height);
+        let start = V2i::new(x, y);
+        let end = start.plus(V2i::new(width as i32, height as i32));
+        let loaded = self.universe.get_loaded_grids();
+
+        // a grid at a time, clipped to the rectangle
+        let first = GridIndex::from_pos(start, GRID_SIZE, GRID_SIZE).grid_offset;
+        let last = GridIndex::from_pos(end.minus(V2i::new(1, 1)), GRID_SIZE, GRID_SIZE).grid_offset;
+        for grid_y in first.y..=last.y {
+            for grid_x in first.x..=last.x {
+                let grid_index = GridIndex {
+                    grid_offset: V2i::new(grid_x, grid_y),
+                };
+                let grid_pos = grid_index.to_pos(GRID_SIZE, GRID_SIZE);
+                let grid_end = grid_pos.plus(V2i::new(GRID_SIZE as i32, GRID_SIZE as i32));
+                let range_start = V2i::new(grid_pos.x.max(start.x), grid_pos.y.max(start.y));
+                let range_end = V2i::new(grid_end.x.min(end.x), grid_end.y.min(end.y));
+                for res in self.universe.cells.get_range(range_start, range_end) {
+                    let pixel = self.cell_pixel(&res, horizon);
+                    let img_pos = res.0.minus(start);
+                    img.put_pixel(
+                        img_pos.x as u32,
+                        img_pos.y as u32,
+                        image::Rgb([(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]),
+                    );
+                }
+                if !loaded.contains(&grid_index) {
+                    self.universe.drop_grid(grid_index);
+                }
+            }
+        }
+
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut bytes, image::ImageFormat::Png)?;
+        Ok(bytes.into_inner())
+    }
+
+    /// Like `stream`, but returns errors instead of logging them
+    pub fn try_stream(&mut self) -> Result<Vec<GridIndex>, Box<dyn std::error::Error>> {
+        self.universe.stream()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_world_resume() {
+        let path = std::env::temp_dir().join(format!("rockies-resume-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let pos = V2i::new(5, -5);
+
+        let mut game = Game::new(16, 16);
+        game.set_store(Box::new(DirStore::open(&path).unwrap()))
+            .unwrap();
+        game.try_stream().unwrap();
+        game.universe.cells.add_cell(Cell {
+            index: CellIndex::default(),
+            color: Material::Dirt.color(1.0),
+            inertia: Inertia {
+                velocity: V2::zero(),
+                force: V2::zero(),
+                pos: pos.to_v2(),
+                mass: Material::Dirt.density(),
+                collision_stats: 0,
+            },
+            material: Material::Dirt,
+            state: CellState::Fixed,
+        });
+        game.universe.player.inertia.pos = V2::new(3.0, -4.0);
+        game.try_stream().unwrap();
+
+        let mut resumed = Game::new(16, 16);
+        resumed
+            .set_store(Box::new(DirStore::open(&path).unwrap()))
+            .unwrap();
+        assert_eq!(resumed.universe.player.inertia.pos, V2::new(3.0, -4.0));
+        let res = resumed
+            .universe
+            .cells
+            .get_range(pos, pos.plus(V2i::new(1, 1)));
+        assert_eq!(res[0].1.len(), 1);
+        assert_eq!(res[0].1[0].borrow().material, Material::Dirt);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_export_png() {
+        let mut game = Game::new(16, 16);
+        let stone = Material::Stone.color(1.0);
+        // a wall in the sky, across the edge between two grids
+        let pos = V2i::new(-1, -300);
+        for x in 0..2 {
+            let cell_pos = pos.plus(V2i::new(x, 0));
+            game.universe
+                .cells
+                .ensure_grid(GridIndex::from_pos(cell_pos, GRID_SIZE, GRID_SIZE));
+            game.universe.cells.add_cell(Cell {
+                index: CellIndex::default(),
+                color: stone,
+                inertia: Inertia {
+                    velocity: V2::zero(),
+                    force: V2::zero(),
+                    pos: cell_pos.to_v2(),
+                    mass: Material::Stone.density(),
+                    collision_stats: 0,
+                },
+                material: Material::Stone,
+                state: CellState::Fixed,
+            });
+        }
+        let loaded = game.universe.get_loaded_grids();
+
+        // spanning grids on both sides of the ground level, most not loaded
+        let horizon = game.ground_level();
+        let png = game.export_png(-200, -310, 400, 450, horizon).unwrap();
+        let img = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(img.dimensions(), (400, 450));
+        let pixel = |pos: V2i| img.get_pixel((pos.x + 200) as u32, (pos.y + 310) as u32).0;
+        assert_eq!(pixel(pos), [stone.r, stone.g, stone.b]);
+        assert_eq!(pixel(pos.plus(V2i::new(1, 0))), [stone.r, stone.g, stone.b]);
+        let background = game.render_background(pos.plus(V2i::new(2, 0)), horizon);
+        assert_eq!(
+            pixel(pos.plus(V2i::new(2, 0))),
+            [
+                (background >> 16) as u8,
+                (background >> 8) as u8,
+                background as u8
+            ]
+        );
+
+        // grids loaded for the export are dropped again
+        assert_eq!(game.universe.get_loaded_grids().len(), loaded.len());
+    }
 }
@@ -10,15 +10,112 @@ use ansi_term::{ANSIGenericString, ANSIStrings};
 use fnv::FnvHashSet;
 
 use libc::{ioctl, winsize, TIOCGWINSZ};
+mod args;
 mod console;
 
-use rockies::Game;
+use rockies::{ArchiveStore, ChunkStore, ColorMapping, DirStore, Game, Preview};
 
 static FRAMES_MS: u128 = 40;
 static TICK_MS: u128 = 20;
 static KBD_MS: u128 = 100;
+static SAVE_MS: u128 = 5000;
+
+// A game with the world, generator etc. given on the command line
+fn new_game(args: args::Args, width: usize, height: usize) -> Game {
+    let store: Option<Box<dyn ChunkStore>> = match (args.world, args.archive) {
+        (Some(path), _) => {
+            Some(Box::new(DirStore::open(&path).unwrap_or_else(|err| {
+                panic!("Failed to open world {}: {err}", path.display())
+            })))
+        }
+        (None, Some(path)) => Some(Box::new(ArchiveStore::open(&path).unwrap_or_else(|err| {
+            panic!("Failed to open world archive {}: {err}", path.display())
+        }))),
+        (None, None) => None,
+    };
+
+    let mut game = Game::new_with_seed(width, height, args.seed.unwrap_or(0));
+    if let Some(generator) = args.generator {
+        game.set_generator(generator);
+    }
+    if let Some(store) = store {
+        game.set_store(store).expect("Failed to load world");
+    }
+    for import in args.imports {
+        let png = std::fs::read(&import.path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", import.path.display()));
+        game.import_image(&png, import.x, import.y, &ColorMapping::default())
+            .unwrap_or_else(|err| panic!("Failed to import {}: {err}", import.path.display()));
+    }
+    game
+}
+
+// Renders a rectangle of the world to a PNG file, without opening the game
+fn export_png(args: args::Args, export: args::Export) {
+    let mut game = new_game(args, export.width as usize, export.height as usize);
+    let horizon = game.ground_level();
+    let png = game
+        .export_png(export.x, export.y, export.width, export.height, horizon)
+        .expect("Failed to render PNG");
+    std::fs::write(&export.path, png)
+        .unwrap_or_else(|err| panic!("Failed to write {}: {err}", export.path.display()));
+}
+
+// Writes an overview of generated grids, and prints their statistics
+fn preview(args: args::Args, area: args::PreviewArea) {
+    let preview = Preview::generate(
+        &args.generator.unwrap_or_default(),
+        args.seed.unwrap_or(0),
+        area.first.0..=area.last.0,
+        area.first.1..=area.last.1,
+    );
+    let png = preview.to_png().expect("Failed to render PNG");
+    std::fs::write(&area.path, png)
+        .unwrap_or_else(|err| panic!("Failed to write {}: {err}", area.path.display()));
+
+    println!("grid\tsolid\tcaves\tliquid\tore");
+    let mut total = (0, 0, 0, 0, 0);
+    for chunk in preview.chunks.iter() {
+        println!(
+            "{},{}\t{:.3}\t{}\t{}\t{}",
+            chunk.grid_x,
+            chunk.grid_y,
+            chunk.solid_fraction(),
+            chunk.caves,
+            chunk.liquid,
+            chunk.ore
+        );
+        total = (
+            total.0 + chunk.cells,
+            total.1 + chunk.solid,
+            total.2 + chunk.caves,
+            total.3 + chunk.liquid,
+            total.4 + chunk.ore,
+        );
+    }
+    println!(
+        "total\t{:.3}\t{}\t{}\t{}",
+        total.1 as f64 / total.0 as f64,
+        total.2,
+        total.3,
+        total.4
+    );
+}
+
+fn main() {
+    let mut args = match args::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{msg}");
+            std::process::exit(2);
+        }
+    };
+    match args.command.take() {
+        Some(args::Command::Export(export)) => return export_png(args, export),
+        Some(args::Command::Preview(area)) => return preview(args, area),
+        None => (),
+    }
 
-fn main() -> () {
     let mut out = stdout();
     console::screen_save(&mut out);
     console::alternate_buffer_enable(&mut out);
@@ -46,6 +143,7 @@ fn main() -> () {
 
     let mut last_tick_time = Instant::now();
     let mut last_kbd_time = Instant::now();
+    let mut last_save_time = Instant::now();
 
     let mut keys: FnvHashSet<String> = FnvHashSet::default();
     let (tx, rx) = std::sync::mpsc::channel();
@@ -54,7 +152,11 @@ fn main() -> () {
     let render_stop = stop.clone();
     let render_handle = std::thread::spawn(move || render_thread(rx, render_stop));
 
-    let mut game = Game::new(winsize.ws_col as usize - 2, winsize.ws_row as usize - 2);
+    let mut game = new_game(
+        args,
+        winsize.ws_col as usize - 2,
+        winsize.ws_row as usize - 2,
+    );
 
     loop {
         // throttle ticks
@@ -67,6 +169,11 @@ fn main() -> () {
         last_tick_time = start;
         let wsize = get_terminal_size(&out);
 
+        if SAVE_MS < start.duration_since(last_save_time).as_millis() {
+            last_save_time = start;
+            game.try_stream().expect("Failed to save grids");
+        }
+
         let frame: Vec<ANSIGenericString<'_, str>> = generate_text_frame(&game, wsize);
         tx.send(frame).expect("Failed to send frame");
 
@@ -92,6 +199,8 @@ fn main() -> () {
 
     render_handle.join().unwrap();
 
+    game.try_stream().expect("Failed to save grids");
+
     console::restore_terminal(&stdin_handle, termios);
     console::cursor_enable(&mut out);
     console::alternate_buffer_disable(&mut out);
@@ -101,7 +210,7 @@ fn main() -> () {
 fn render_thread(
     rx: std::sync::mpsc::Receiver<Vec<ANSIGenericString<'_, str>>>,
     stop: Arc<RwLock<bool>>,
-) -> () {
+) {
     let mut out = stdout();
     let mut last_frame_time = Instant::now();
     loop {
@@ -195,11 +304,11 @@ fn process_keyboard(
         }
     }
     //print!("keys: {:?}, next_keys: {:?}\n\r", keys, next_keys);
-    keys.clone_from(&mut next_keys);
+    keys.clone_from(&next_keys);
     true
 }
 
-fn text_render(out: &mut std::io::Stdout, frame: Vec<ANSIGenericString<'_, str>>) -> () {
+fn text_render(out: &mut std::io::Stdout, frame: Vec<ANSIGenericString<'_, str>>) {
     let output = ANSIStrings(frame.as_slice());
 
     console::home(out);
@@ -217,7 +326,7 @@ fn generate_text_frame(game: &Game, wsize: winsize) -> Vec<ANSIGenericString<'st
 
     let mut frame: Vec<ANSIGenericString<str>> = Vec::default();
 
-    for line in game.pixels_vec().chunks(game.width() as usize) {
+    for line in game.pixels_vec().chunks(game.width()) {
         for _ in 0..padding {
             frame.push(" ".into());
         }
@@ -0,0 +1,306 @@
+use crate::color::Color;
+
+/// The kind of "stuff" a cell is made of. Physical parameters (mass,
+/// elasticity, ...) and the color palette are derived from the material, so
+/// cells of the same material behave the same.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Material {
+    Sand,
+    Stone,
+    Dirt,
+    Water,
+    Grass,
+    Snow,
+    Mud,
+    Coal,
+    Iron,
+    Gold,
+    Gem,
+}
+
+/// Physical and visual parameters of a material.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialProps {
+    /// Mass of a single moving cell of this material
+    pub density: i32,
+    /// Restitution when colliding (0..1)
+    pub elasticity: f64,
+    /// Kinetic friction coefficient, for sliding cells
+    pub friction: f64,
+    /// Static friction coefficient: a cell rests on a slope up to
+    /// `atan(static_friction)` steep (its angle of repose)
+    pub static_friction: f64,
+    /// Resistance to being broken (0..1)
+    pub hardness: f64,
+    /// Base palette: hue (0-360), saturation (0-1), value (0-1)
+    pub hue: f64,
+    pub saturation: f64,
+    pub value: f64,
+}
+
+const SAND: MaterialProps = MaterialProps {
+    density: 2,
+    elasticity: 0.2,
+    friction: 0.6,
+    static_friction: 0.7,
+    hardness: 0.1,
+    hue: 45.0,
+    saturation: 0.6,
+    value: 0.9,
+};
+
+const STONE: MaterialProps = MaterialProps {
+    density: 3,
+    elasticity: 0.3,
+    friction: 0.8,
+    static_friction: 0.9,
+    hardness: 0.8,
+    hue: 30.0,
+    saturation: 1.0,
+    value: 0.5,
+};
+
+const DIRT: MaterialProps = MaterialProps {
+    density: 2,
+    elasticity: 0.1,
+    friction: 0.7,
+    static_friction: 0.8,
+    hardness: 0.3,
+    hue: 25.0,
+    saturation: 0.7,
+    value: 0.35,
+};
+
+const WATER: MaterialProps = MaterialProps {
+    density: 1,
+    elasticity: 0.0,
+    friction: 0.0,
+    static_friction: 0.0,
+    hardness: 0.0,
+    hue: 210.0,
+    saturation: 0.8,
+    value: 0.9,
+};
+
+const GRASS: MaterialProps = MaterialProps {
+    density: 2,
+    elasticity: 0.1,
+    friction: 0.8,
+    static_friction: 0.9,
+    hardness: 0.2,
+    hue: 110.0,
+    saturation: 0.7,
+    value: 0.6,
+};
+
+const SNOW: MaterialProps = MaterialProps {
+    density: 1,
+    elasticity: 0.1,
+    friction: 0.2,
+    static_friction: 0.3,
+    hardness: 0.1,
+    hue: 200.0,
+    saturation: 0.08,
+    value: 0.95,
+};
+
+const MUD: MaterialProps = MaterialProps {
+    density: 2,
+    elasticity: 0.0,
+    friction: 0.9,
+    static_friction: 1.0,
+    hardness: 0.1,
+    hue: 35.0,
+    saturation: 0.5,
+    value: 0.3,
+};
+
+const COAL: MaterialProps = MaterialProps {
+    density: 2,
+    elasticity: 0.2,
+    friction: 0.7,
+    static_friction: 0.8,
+    hardness: 0.5,
+    hue: 0.0,
+    saturation: 0.0,
+    value: 0.15,
+};
+
+const IRON: MaterialProps = MaterialProps {
+    density: 5,
+    elasticity: 0.3,
+    friction: 0.8,
+    static_friction: 0.9,
+    hardness: 0.9,
+    hue: 15.0,
+    saturation: 0.55,
+    value: 0.55,
+};
+
+const GOLD: MaterialProps = MaterialProps {
+    density: 8,
+    elasticity: 0.2,
+    friction: 0.6,
+    static_friction: 0.7,
+    hardness: 0.4,
+    hue: 50.0,
+    saturation: 0.9,
+    value: 1.0,
+};
+
+const GEM: MaterialProps = MaterialProps {
+    density: 3,
+    elasticity: 0.5,
+    friction: 0.3,
+    static_friction: 0.4,
+    hardness: 1.0,
+    hue: 290.0,
+    saturation: 0.8,
+    value: 0.9,
+};
+
+impl Material {
+    pub const ALL: [Material; 11] = [
+        Material::Sand,
+        Material::Stone,
+        Material::Dirt,
+        Material::Water,
+        Material::Grass,
+        Material::Snow,
+        Material::Mud,
+        Material::Coal,
+        Material::Iron,
+        Material::Gold,
+        Material::Gem,
+    ];
+
+    /// Minerals found underground, worth digging for
+    pub const ORES: [Material; 4] = [
+        Material::Coal,
+        Material::Iron,
+        Material::Gold,
+        Material::Gem,
+    ];
+
+    /// What the player can shoot: everything but ores, which have to be dug
+    /// up
+    pub const SHOOTABLE: [Material; 7] = [
+        Material::Sand,
+        Material::Stone,
+        Material::Dirt,
+        Material::Water,
+        Material::Grass,
+        Material::Snow,
+        Material::Mud,
+    ];
+
+    pub fn props(self) -> &'static MaterialProps {
+        match self {
+            Material::Sand => &SAND,
+            Material::Stone => &STONE,
+            Material::Dirt => &DIRT,
+            Material::Water => &WATER,
+            Material::Grass => &GRASS,
+            Material::Snow => &SNOW,
+            Material::Mud => &MUD,
+            Material::Coal => &COAL,
+            Material::Iron => &IRON,
+            Material::Gold => &GOLD,
+            Material::Gem => &GEM,
+        }
+    }
+
+    pub fn density(self) -> i32 {
+        self.props().density
+    }
+
+    pub fn elasticity(self) -> f64 {
+        self.props().elasticity
+    }
+
+    pub fn friction(self) -> f64 {
+        self.props().friction
+    }
+
+    pub fn static_friction(self) -> f64 {
+        self.props().static_friction
+    }
+
+    pub fn hardness(self) -> f64 {
+        self.props().hardness
+    }
+
+    pub fn is_ore(self) -> bool {
+        Self::ORES.contains(&self)
+    }
+
+    /// Liquids flow, and let denser cells sink in them (lighter ones float
+    /// up). At rest they stay put until a cell next to them is taken away
+    pub fn is_liquid(self) -> bool {
+        matches!(self, Material::Water)
+    }
+
+    /// A color from the material's palette.
+    ///
+    /// # Arguments
+    ///
+    /// * `shade` - Brightness relative to the base color (0-1)
+    pub fn color(self, shade: f64) -> Color {
+        let props = self.props();
+        Color::hsv(props.hue, props.saturation, props.value * shade)
+    }
+
+    /// The material whose base color (at full shade) is closest to `color`
+    pub fn closest(color: Color) -> Material {
+        *Self::ALL
+            .iter()
+            .min_by_key(|m| m.color(1.0).distance_sqr(color))
+            .unwrap()
+    }
+
+    /// The next material in `SHOOTABLE`, wrapping around (to the first one
+    /// for materials that aren't shootable).
+    pub fn next(self) -> Material {
+        match Self::SHOOTABLE.iter().position(|m| *m == self) {
+            Some(pos) => Self::SHOOTABLE[(pos + 1) % Self::SHOOTABLE.len()],
+            None => Self::SHOOTABLE[0],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_material_next_cycles() {
+        let mut material = Material::Sand;
+        for _ in 0..Material::SHOOTABLE.len() {
+            material = material.next();
+            assert!(!material.is_ore());
+        }
+        assert_eq!(material, Material::Sand);
+        assert_eq!(Material::Gold.next(), Material::Sand);
+    }
+
+    #[test]
+    fn test_material_color_shade() {
+        assert_eq!(Material::Stone.color(1.0), Color::hsv(30.0, 1.0, 0.5));
+        assert_eq!(Material::Stone.color(0.0), Color::rgb(0, 0, 0));
+    }
+
+    #[test]
+    fn test_material_closest() {
+        for material in Material::ALL {
+            assert_eq!(Material::closest(material.color(1.0)), material);
+        }
+        assert_eq!(Material::closest(Color::rgb(120, 60, 10)), Material::Stone);
+    }
+
+    #[test]
+    fn test_material_density_positive() {
+        for material in Material::ALL {
+            assert!(material.density() > 0);
+        }
+    }
+}
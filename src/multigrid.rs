@@ -16,6 +16,18 @@ pub struct CellIndex {
     pub index: usize,
 }
 
+/// Whether a loaded grid differs from its stored copy (see `tla/GridSystem.tla`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridStatus {
+    /// Freshly generated and never modified: can be regenerated, so there is
+    /// no need to ever store it
+    Pristine,
+    /// Same as the stored copy
+    Unmodified,
+    /// Modified since it was last stored (or generated)
+    Dirty,
+}
+
 // Keeps track of the visible part of the world
 pub struct UniverseGrid<T> {
     pub width: usize,
@@ -23,32 +35,72 @@ pub struct UniverseGrid<T> {
 
     offset: V2i,
     grid: Grid<T>,
+    status: GridStatus,
 }
 
 impl<T: Debug> UniverseGrid<T> {
-    pub fn to_bytes(&self) -> Result<JsValue, serde_wasm_bindgen::Error>
+    fn from_grid(
+        grid: Grid<T>,
+        grid_index: GridIndex,
+        grid_width: usize,
+        grid_height: usize,
+        status: GridStatus,
+    ) -> Self {
+        UniverseGrid {
+            grid,
+            width: grid_width,
+            height: grid_height,
+            offset: grid_index.to_pos(grid_width, grid_height),
+            status,
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.status == GridStatus::Dirty
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.status = GridStatus::Dirty;
+    }
+
+    /// Marks a freshly generated grid as pristine, undoing the `Dirty` mark
+    /// left by the generator's `put`s
+    pub fn mark_pristine(&mut self) {
+        self.status = GridStatus::Pristine;
+    }
+
+    /// Marks the grid as stored. A pristine grid stays pristine, since it
+    /// doesn't need to be stored in the first place.
+    pub fn mark_saved(&mut self) {
+        if self.status == GridStatus::Dirty {
+            self.status = GridStatus::Unmodified;
+        }
+    }
+
+    pub fn to_vec(&self) -> bincode::Result<Vec<u8>>
     where
         T: serde::Serialize + Clone,
     {
-        self.grid.to_bytes()
+        self.grid.to_vec()
     }
 
-    pub fn from_bytes(
-        bytes: JsValue,
+    pub fn from_slice(
+        bytes: &[u8],
         grid_index: GridIndex,
         grid_width: usize,
         grid_height: usize,
-    ) -> Result<Self, serde_wasm_bindgen::Error>
+    ) -> bincode::Result<Self>
     where
         T: serde::de::DeserializeOwned + Clone,
     {
-        let grid = Grid::from_bytes(bytes)?;
-        Ok(UniverseGrid {
+        let grid = Grid::from_slice(bytes)?;
+        Ok(Self::from_grid(
             grid,
-            width: grid_width,
-            height: grid_height,
-            offset: grid_index.to_pos(grid_width, grid_height),
-        })
+            grid_index,
+            grid_width,
+            grid_height,
+            GridStatus::Unmodified,
+        ))
     }
 
     pub fn is_in_bounds(&self, pos: V2i) -> bool {
@@ -65,6 +117,7 @@ impl<T: Debug> UniverseGrid<T> {
             "pos {pos:?} not in bounds, {:?}",
             self.offset
         );
+        self.mark_dirty();
         let rpos = pos.minus(self.offset);
         self.grid.remove(
             usize::try_from(rpos.x).unwrap(),
@@ -75,6 +128,7 @@ impl<T: Debug> UniverseGrid<T> {
 
     pub fn put(&mut self, pos: V2i, cell_idx: GridCellRef<T>) {
         assert!(self.is_in_bounds(pos));
+        self.mark_dirty();
         let rpos = pos.minus(self.offset);
         self.grid.put(
             usize::try_from(rpos.x).unwrap(),
@@ -83,7 +137,7 @@ impl<T: Debug> UniverseGrid<T> {
         )
     }
 
-    pub fn get(&self, pos: V2i) -> crate::grid::GetResult<T> {
+    pub fn get(&self, pos: V2i) -> crate::grid::GetResult<'_, T> {
         assert!(self.is_in_bounds(pos));
         let rpos = pos.minus(self.offset);
         self.grid.get(
@@ -93,12 +147,13 @@ impl<T: Debug> UniverseGrid<T> {
     }
 
     pub(crate) fn new(grid_index: GridIndex, grid_width: usize, grid_height: usize) -> Self {
-        UniverseGrid {
-            grid: Grid::new(grid_width, grid_height),
-            width: grid_width,
-            height: grid_height,
-            offset: grid_index.to_pos(grid_width, grid_height),
-        }
+        Self::from_grid(
+            Grid::new(grid_width, grid_height),
+            grid_index,
+            grid_width,
+            grid_height,
+            GridStatus::Pristine,
+        )
     }
 }
 
@@ -120,6 +175,7 @@ impl GridIndex {
         }
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_pos(&self, width: usize, height: usize) -> V2i {
         V2i::new(
             self.grid_offset.x * width as i32,
@@ -128,11 +184,23 @@ impl GridIndex {
     }
 
     #[wasm_bindgen]
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_js(&self) -> Result<JsValue, JsValue> {
         Ok(serde_wasm_bindgen::to_value(&self)?)
     }
 }
 
+const NEIGHBOR_OFFSETS: [V2i; 8] = [
+    V2i::new(-1, -1),
+    V2i::new(0, -1),
+    V2i::new(1, -1),
+    V2i::new(-1, 0),
+    V2i::new(1, 0),
+    V2i::new(-1, 1),
+    V2i::new(0, 1),
+    V2i::new(1, 1),
+];
+
 pub struct MultiGrid<T> {
     grids: FnvHashMap<GridIndex, UniverseGrid<T>>,
 
@@ -180,14 +248,61 @@ impl<T: Debug> MultiGrid<T> {
         GridIndex::from_pos(pos, self.grid_width, self.grid_height)
     }
 
-    pub fn update_cell_pos(&mut self, cell_idx: &GridCellRef<T>, old_pos: V2i, new_pos: V2i) {
+    /// All items at `pos` and the 8 positions around it, like
+    /// `Grid::get(..).neighbors` but also across the edges of the grid, from
+    /// adjacent grids (if they are loaded).
+    pub fn neighbors(&self, pos: V2i) -> impl Iterator<Item = &GridCellRef<T>> + '_ {
+        let grid_index = self.pos_to_index(pos);
+        let own = match self.get(grid_index) {
+            Some(grid) => grid.get(pos).neighbors,
+            None => &[],
+        };
+
+        // a grid's halo only has its own items, the other grids' are looked up
+        let rpos = pos.minus(grid_index.to_pos(self.grid_width, self.grid_height));
+        let at_edge = rpos.x == 0
+            || rpos.y == 0
+            || rpos.x == self.grid_width as i32 - 1
+            || rpos.y == self.grid_height as i32 - 1;
+        let offsets: &[V2i] = if at_edge { &NEIGHBOR_OFFSETS } else { &[] };
+        let across = offsets.iter().flat_map(move |offset| {
+            let npos = pos.plus(*offset);
+            let other_index = self.pos_to_index(npos);
+            match self.get(other_index) {
+                Some(other) if other_index != grid_index => other.get(npos).value,
+                _ => &[],
+            }
+        });
+
+        own.iter().chain(across)
+    }
+
+    /// Moves an item between positions (and grids). Items can't move into a
+    /// grid that isn't loaded: then nothing changes and this returns false,
+    /// leaving the item where it was (at the border) until that grid loads.
+    pub fn update_cell_pos(
+        &mut self,
+        cell_idx: &GridCellRef<T>,
+        old_pos: V2i,
+        new_pos: V2i,
+    ) -> bool {
         // update grid:
         if old_pos != new_pos {
-            self.get_mut(self.pos_to_index(old_pos))
-                .map(|grid| grid.remove(old_pos, cell_idx));
-            self.get_mut(self.pos_to_index(new_pos))
-                .map(|grid| grid.put(new_pos, cell_idx.clone()));
+            let new_index = self.pos_to_index(new_pos);
+            if self.get(new_index).is_none() {
+                return false;
+            }
+            if let Some(grid) = self.get_mut(self.pos_to_index(old_pos)) {
+                grid.remove(old_pos, cell_idx);
+            }
+            self.get_mut(new_index)
+                .unwrap()
+                .put(new_pos, cell_idx.clone());
+        } else if let Some(grid) = self.get_mut(self.pos_to_index(old_pos)) {
+            // the cell itself (sub-cell position, velocity) still changed
+            grid.mark_dirty();
         }
+        true
     }
 
     // Returns only missing grid indexes (that need to be loaded)
@@ -195,8 +310,8 @@ impl<T: Debug> MultiGrid<T> {
         let r = drop_radius as i32;
         let center_grid = GridIndex::from_pos(center, self.grid_width, self.grid_height);
         let mut res: Vec<GridIndex> = Vec::new();
-        for x in -r..r {
-            for y in -r..r {
+        for x in -r..=r {
+            for y in -r..=r {
                 let grid_index = GridIndex {
                     grid_offset: V2i::new(
                         center_grid.grid_offset.x + x,
@@ -218,8 +333,8 @@ impl<T: Debug> MultiGrid<T> {
         let center_grid = GridIndex::from_pos(center, self.grid_width, self.grid_height);
 
         self.grids
-            .iter()
-            .map(|(grid_index, _)| *grid_index)
+            .keys()
+            .copied()
             .filter(|grid_index| {
                 let dx = grid_index.grid_offset.x - center_grid.grid_offset.x;
                 let dy = grid_index.grid_offset.y - center_grid.grid_offset.y;
@@ -230,23 +345,332 @@ impl<T: Debug> MultiGrid<T> {
 
     // Gets only existing far grids (that can be saved)
     pub fn get_loaded_grids(&self) -> Vec<GridIndex> {
+        self.grids.keys().copied().collect()
+    }
+
+    // Grids that were modified since they were last stored
+    pub fn get_dirty_grids(&self) -> Vec<GridIndex> {
         self.grids
             .iter()
+            .filter(|(_, grid)| grid.is_dirty())
             .map(|(grid_index, _)| *grid_index)
             .collect()
     }
 
+    pub fn mark_saved(&mut self, grid_index: GridIndex) {
+        if let Some(grid) = self.get_mut(grid_index) {
+            grid.mark_saved();
+        }
+    }
+
+    // Dirty grids must be stored first, so they are never dropped
     pub fn drop_grid(&mut self, grid_index: GridIndex) -> Option<UniverseGrid<T>> {
         //println!("dropping grid: {grid_index:?}");
+        if self.get(grid_index)?.is_dirty() {
+            log!("not dropping dirty grid: {grid_index:?}");
+            return None;
+        }
         self.remove(grid_index)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::multigrid::GridIndex;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use fnv::FnvHashMap;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::grid_system::{
+        GridDirtyStatus, GridLoadStatus, GridPos, GridStoreStatus, GridSystem, PlayerId,
+    };
+    use crate::multigrid::{GridIndex, GridStatus, MultiGrid, UniverseGrid};
     use crate::v2::V2i;
 
+    const MODEL_GRIDS: u32 = 6;
+    const MODEL_GRID_SIZE: usize = 4;
+    const MODEL_PLAYER: PlayerId = PlayerId::new(0);
+
+    // Drives a `MultiGrid` (plus a store and a player, like `Universe` does)
+    // side by side with the TLA+ model in `tla/grid_system.rs`, checking
+    // after every step that the two agree.
+    struct Conformance {
+        model: GridSystem,
+        grids: MultiGrid<i32>,
+        store: FnvHashMap<GridIndex, Vec<u8>>,
+        player: GridIndex,
+    }
+
+    fn model_index(pos: GridPos) -> GridIndex {
+        GridIndex {
+            grid_offset: V2i::new(pos.value() as i32 - MODEL_GRIDS as i32 / 2, 0),
+        }
+    }
+
+    impl Conformance {
+        fn new() -> Conformance {
+            let model = GridSystem::new(
+                (0..MODEL_GRIDS).map(GridPos::new).collect(),
+                [MODEL_PLAYER].into(),
+            );
+            let player = model_index(model.get_player_position(MODEL_PLAYER).unwrap());
+            let mut res = Conformance {
+                model,
+                grids: MultiGrid::new(MODEL_GRID_SIZE, MODEL_GRID_SIZE),
+                store: FnvHashMap::default(),
+                player,
+            };
+            // the player starts out on a loaded grid
+            let player_pos = res.model.get_player_position(MODEL_PLAYER).unwrap();
+            assert!(res.load(player_pos));
+            res
+        }
+
+        fn cell_pos(pos: GridPos, rng: &mut SmallRng) -> V2i {
+            let size = MODEL_GRID_SIZE as i32;
+            model_index(pos)
+                .to_pos(MODEL_GRID_SIZE, MODEL_GRID_SIZE)
+                .plus(V2i::new(
+                    rng.random_range(0..size),
+                    rng.random_range(0..size),
+                ))
+        }
+
+        fn move_player(&mut self, pos: GridPos) -> bool {
+            let ok = self.grids.get(model_index(pos)).is_some();
+            if ok {
+                self.player = model_index(pos);
+            }
+            assert_eq!(self.model.player_moves(MODEL_PLAYER, pos), ok);
+            ok
+        }
+
+        fn load(&mut self, pos: GridPos) -> bool {
+            let grid_index = model_index(pos);
+            let ok = self.grids.get(grid_index).is_none();
+            if ok {
+                let grid = match self.store.get(&grid_index) {
+                    Some(bytes) => UniverseGrid::from_slice(
+                        bytes,
+                        grid_index,
+                        MODEL_GRID_SIZE,
+                        MODEL_GRID_SIZE,
+                    )
+                    .unwrap(),
+                    None => {
+                        // "generate" a grid
+                        let mut grid =
+                            UniverseGrid::new(grid_index, MODEL_GRID_SIZE, MODEL_GRID_SIZE);
+                        let origin = grid_index.to_pos(MODEL_GRID_SIZE, MODEL_GRID_SIZE);
+                        grid.put(origin, Rc::new(RefCell::new(-1)));
+                        grid.mark_pristine();
+                        grid
+                    }
+                };
+                self.grids.insert(grid_index, grid);
+            }
+            let model_ok = self.model.load_stored_grid(pos) || self.model.load_missing_grid(pos);
+            assert_eq!(model_ok, ok);
+            ok
+        }
+
+        fn modify(&mut self, pos: GridPos, rng: &mut SmallRng) -> bool {
+            let cell_pos = Self::cell_pos(pos, rng);
+            let other_pos = Self::cell_pos(pos, rng);
+            let Some(grid) = self.grids.get_mut(model_index(pos)) else {
+                assert!(!self.model.mark_dirty(pos));
+                return false;
+            };
+            let was_dirty = grid.is_dirty();
+            if rng.random_bool(0.5) {
+                grid.put(cell_pos, Rc::new(RefCell::new(rng.random())));
+            } else {
+                let cell = Rc::new(RefCell::new(0));
+                grid.put(cell_pos, cell.clone());
+                self.grids.update_cell_pos(&cell, cell_pos, other_pos);
+            }
+            // the model only allows marking clean grids dirty
+            assert_eq!(self.model.mark_dirty(pos), !was_dirty);
+            true
+        }
+
+        fn save(&mut self, pos: GridPos) -> bool {
+            let grid_index = model_index(pos);
+            let ok = match self.grids.get(grid_index) {
+                Some(grid) => grid.status != GridStatus::Pristine,
+                None => false,
+            };
+            if ok {
+                let bytes = self.grids.get(grid_index).unwrap().to_vec().unwrap();
+                self.store.insert(grid_index, bytes);
+                self.grids.mark_saved(grid_index);
+            }
+            assert_eq!(self.model.store_grid(pos), ok);
+            ok
+        }
+
+        fn drop_grid(&mut self, pos: GridPos) -> bool {
+            let grid_index = model_index(pos);
+            // like `get_far_grids`, never drop the grid the player is on
+            if grid_index == self.player {
+                return false;
+            }
+            let was_dirty = self.grids.get(grid_index).is_some_and(|g| g.is_dirty());
+            let ok = self.grids.drop_grid(grid_index).is_some();
+            assert!(!(was_dirty && ok), "dropped dirty grid {grid_index:?}");
+            assert_eq!(self.model.unload_pristine_unmodified_grid(pos), ok);
+            ok
+        }
+
+        fn check(&self) {
+            for pos in (0..MODEL_GRIDS).map(GridPos::new) {
+                let grid_index = model_index(pos);
+                let grid = self.grids.get(grid_index);
+
+                let load_status = match grid {
+                    Some(_) => GridLoadStatus::Loaded,
+                    None => GridLoadStatus::NotLoaded,
+                };
+                assert_eq!(self.model.get_grid_load_status(pos), Some(load_status));
+
+                let store_status = match self.store.contains_key(&grid_index) {
+                    true => GridStoreStatus::Stored,
+                    false => GridStoreStatus::NotStored,
+                };
+                assert_eq!(self.model.get_grid_store_status(pos), Some(store_status));
+
+                // the model keeps the last status of unloaded grids, only
+                // loaded ones are comparable (KnownDirtyStatus)
+                if let Some(grid) = grid {
+                    let dirty_status = match grid.status {
+                        GridStatus::Pristine => GridDirtyStatus::Pristine,
+                        GridStatus::Unmodified => GridDirtyStatus::Unmodified,
+                        GridStatus::Dirty => GridDirtyStatus::Dirty,
+                    };
+                    assert_eq!(self.model.get_grid_dirty_status(pos), Some(dirty_status));
+                }
+            }
+            assert!(self.grids.get(self.player).is_some());
+            assert_eq!(
+                self.model
+                    .get_player_position(MODEL_PLAYER)
+                    .map(model_index),
+                Some(self.player)
+            );
+        }
+    }
+
+    #[test]
+    fn test_neighbors_across_grids() {
+        let mut grids: MultiGrid<i32> = MultiGrid::new(10, 10);
+        for x in [-1, 0] {
+            let grid_index = GridIndex {
+                grid_offset: V2i::new(x, 0),
+            };
+            grids.insert(grid_index, UniverseGrid::new(grid_index, 10, 10));
+        }
+        let put = |grids: &mut MultiGrid<i32>, pos: V2i, value: i32| {
+            let grid_index = grids.pos_to_index(pos);
+            grids
+                .get_mut(grid_index)
+                .unwrap()
+                .put(pos, Rc::new(RefCell::new(value)));
+        };
+        put(&mut grids, V2i::new(-1, 5), 1);
+        put(&mut grids, V2i::new(0, 6), 2);
+        put(&mut grids, V2i::new(1, 5), 3);
+        // (0, 0) is loaded, but (0, -1) isn't
+        put(&mut grids, V2i::new(0, 0), 4);
+
+        let values = |pos: V2i| -> Vec<i32> {
+            let mut values: Vec<i32> = grids.neighbors(pos).map(|v| *v.borrow()).collect();
+            values.sort();
+            values
+        };
+        assert_eq!(values(V2i::new(0, 5)), vec![1, 2, 3]);
+        assert_eq!(values(V2i::new(-1, 6)), vec![1, 2]);
+        assert_eq!(values(V2i::new(-2, 5)), vec![1]);
+        assert_eq!(values(V2i::new(0, 0)), vec![4]);
+        assert_eq!(values(V2i::new(0, -1)), vec![4]);
+    }
+
+    #[test]
+    fn test_conformance_with_model() {
+        for seed in 0..20 {
+            let mut rng = SmallRng::seed_from_u64(seed);
+            let mut system = Conformance::new();
+            system.check();
+            let mut succeeded = [0; 5];
+            for _ in 0..1000 {
+                let pos = GridPos::new(rng.random_range(0..MODEL_GRIDS));
+                let action = rng.random_range(0..5);
+                let ok = match action {
+                    0 => system.move_player(pos),
+                    1 => system.load(pos),
+                    2 => system.modify(pos, &mut rng),
+                    3 => system.save(pos),
+                    _ => system.drop_grid(pos),
+                };
+                if ok {
+                    succeeded[action] += 1;
+                }
+                system.check();
+            }
+            // every kind of action was exercised
+            assert!(succeeded.iter().all(|count| *count > 0), "{succeeded:?}");
+        }
+    }
+
+    #[test]
+    fn test_grid_status() {
+        let mut grids: MultiGrid<i32> = MultiGrid::new(10, 10);
+        let grid_index = GridIndex {
+            grid_offset: V2i::new(0, 0),
+        };
+        grids.insert(grid_index, UniverseGrid::new(grid_index, 10, 10));
+        assert_eq!(grids.get(grid_index).unwrap().status, GridStatus::Pristine);
+        assert!(grids.get_dirty_grids().is_empty());
+
+        let cell = Rc::new(RefCell::new(1));
+        grids
+            .get_mut(grid_index)
+            .unwrap()
+            .put(V2i::new(1, 1), cell.clone());
+        assert_eq!(grids.get_dirty_grids(), vec![grid_index]);
+        assert!(grids.drop_grid(grid_index).is_none());
+
+        grids.mark_saved(grid_index);
+        assert_eq!(
+            grids.get(grid_index).unwrap().status,
+            GridStatus::Unmodified
+        );
+
+        grids.update_cell_pos(&cell, V2i::new(1, 1), V2i::new(1, 1));
+        assert!(grids.get(grid_index).unwrap().is_dirty());
+        grids.mark_saved(grid_index);
+
+        assert!(grids.drop_grid(grid_index).is_some());
+        assert!(grids.get_loaded_grids().is_empty());
+    }
+
+    #[test]
+    fn test_pristine_grid_droppable() {
+        let mut grids: MultiGrid<i32> = MultiGrid::new(10, 10);
+        let grid_index = GridIndex {
+            grid_offset: V2i::new(0, 0),
+        };
+        let mut grid = UniverseGrid::new(grid_index, 10, 10);
+        grid.put(V2i::new(1, 1), Rc::new(RefCell::new(1)));
+        grid.mark_pristine();
+        grids.insert(grid_index, grid);
+
+        grids.mark_saved(grid_index);
+        assert_eq!(grids.get(grid_index).unwrap().status, GridStatus::Pristine);
+        assert!(grids.drop_grid(grid_index).is_some());
+    }
+
     #[test]
     fn test_grid_index_from_pos() {
         let width = 10;
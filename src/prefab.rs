@@ -0,0 +1,60 @@
+use crate::color::Color;
+use crate::material::Material;
+use crate::v2::V2i;
+
+/// A structure stamped into generated terrain, made from `prefabs/*.png` by
+/// the build script: transparent pixels keep the terrain, white pixels clear
+/// it, and any other pixel is a wall of the material closest to its color.
+#[derive(Debug)]
+pub struct Prefab {
+    #[allow(dead_code)]
+    pub name: &'static str,
+    pub width: usize,
+    pub height: usize,
+    pixels: &'static [Option<Color>],
+}
+
+include!(concat!(env!("OUT_DIR"), "/prefabs.rs"));
+
+const AIR: Color = Color {
+    r: 255,
+    g: 255,
+    b: 255,
+};
+
+/// What a prefab does to the terrain at one of its pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PrefabCell {
+    Keep,
+    Air,
+    Wall(Material, Color),
+}
+
+impl Prefab {
+    /// `pos` is relative to the prefab's top left corner
+    pub fn cell(&self, pos: V2i) -> PrefabCell {
+        match self.pixels[pos.y as usize * self.width + pos.x as usize] {
+            None => PrefabCell::Keep,
+            Some(AIR) => PrefabCell::Air,
+            Some(color) => PrefabCell::Wall(Material::closest(color), color),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefabs_loaded() {
+        assert!(!PREFABS.is_empty());
+        for prefab in PREFABS.iter() {
+            assert_eq!(prefab.pixels.len(), prefab.width * prefab.height);
+            let walls = (0..prefab.height as i32)
+                .flat_map(|y| (0..prefab.width as i32).map(move |x| V2i::new(x, y)))
+                .filter(|pos| matches!(prefab.cell(*pos), PrefabCell::Wall(..)))
+                .count();
+            assert!(walls > 0, "{} has no walls", prefab.name);
+        }
+    }
+}
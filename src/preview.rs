@@ -0,0 +1,147 @@
+use std::ops::RangeInclusive;
+
+use crate::generator::GeneratorConfig;
+use crate::multigrid::{GridIndex, UniverseGrid};
+use crate::v2::V2i;
+use crate::GRID_SIZE;
+
+// Empty cells above and below the ground level
+const SKY: [u8; 3] = [0xCC, 0xCC, 0xFF];
+const CAVE: [u8; 3] = [0x11, 0x11, 0x11];
+
+/// What a generator made of one grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChunkStats {
+    pub grid_x: i32,
+    pub grid_y: i32,
+    pub cells: usize,
+    /// Cells with anything but liquid in them
+    pub solid: usize,
+    /// Empty or flooded cells below the ground level
+    pub caves: usize,
+    pub liquid: usize,
+    pub ore: usize,
+}
+
+impl ChunkStats {
+    pub fn solid_fraction(&self) -> f64 {
+        self.solid as f64 / self.cells as f64
+    }
+}
+
+/// An overview of generated grids, without a game: an image at one pixel per
+/// cell, and statistics per grid
+pub struct Preview {
+    pub image: image::RgbImage,
+    pub chunks: Vec<ChunkStats>,
+}
+
+impl Preview {
+    /// Generates the grids in the given ranges of grid indices
+    pub fn generate(
+        config: &GeneratorConfig,
+        seed: u32,
+        grids_x: RangeInclusive<i32>,
+        grids_y: RangeInclusive<i32>,
+    ) -> Preview {
+        let grid_size = GRID_SIZE;
+        let mut generator = config.build(seed);
+        let columns = grids_x.clone().count();
+        let rows = grids_y.clone().count();
+        let mut image =
+            image::RgbImage::new((columns * grid_size) as u32, (rows * grid_size) as u32);
+        let mut chunks = Vec::with_capacity(columns * rows);
+
+        for (row, grid_y) in grids_y.enumerate() {
+            for (column, grid_x) in grids_x.clone().enumerate() {
+                let grid_index = GridIndex {
+                    grid_offset: V2i::new(grid_x, grid_y),
+                };
+                let mut grid = UniverseGrid::new(grid_index, grid_size, grid_size);
+                generator.generate_pristine_grid(&mut grid, grid_index, grid_size, grid_size);
+
+                let mut stats = ChunkStats {
+                    grid_x,
+                    grid_y,
+                    cells: grid_size * grid_size,
+                    solid: 0,
+                    caves: 0,
+                    liquid: 0,
+                    ore: 0,
+                };
+                let base_pos = grid_index.to_pos(grid_size, grid_size);
+                for y in 0..grid_size {
+                    for x in 0..grid_size {
+                        let pos = base_pos.plus(V2i::new(x as i32, y as i32));
+                        let pixel = match grid.get(pos).value.first() {
+                            Some(cell) => {
+                                let cell = cell.borrow();
+                                if cell.material.is_liquid() {
+                                    stats.liquid += 1;
+                                    // the ground level is at y = grid height
+                                    stats.caves += (pos.y >= grid_size as i32) as usize;
+                                } else {
+                                    stats.solid += 1;
+                                }
+                                stats.ore += cell.material.is_ore() as usize;
+                                [cell.color.r, cell.color.g, cell.color.b]
+                            }
+                            None if pos.y >= grid_size as i32 => {
+                                stats.caves += 1;
+                                CAVE
+                            }
+                            None => SKY,
+                        };
+                        image.put_pixel(
+                            (column * grid_size + x) as u32,
+                            (row * grid_size + y) as u32,
+                            image::Rgb(pixel),
+                        );
+                    }
+                }
+                chunks.push(stats);
+            }
+        }
+        Preview { image, chunks }
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, image::ImageError> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        self.image.write_to(&mut bytes, image::ImageFormat::Png)?;
+        Ok(bytes.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_flat() {
+        let preview = Preview::generate(&GeneratorConfig::Flat, 0, -1..=0, 0..=1);
+        assert_eq!(preview.image.dimensions(), (256, 256));
+        let fractions: Vec<(i32, f64)> = preview
+            .chunks
+            .iter()
+            .map(|chunk| (chunk.grid_y, chunk.solid_fraction()))
+            .collect();
+        assert_eq!(fractions, vec![(0, 0.0), (0, 0.0), (1, 1.0), (1, 1.0)]);
+        assert!(preview.chunks.iter().all(|chunk| chunk.caves == 0));
+        assert_eq!(preview.image.get_pixel(0, 0).0, SKY);
+    }
+
+    #[test]
+    fn test_preview_noise_has_caves() {
+        let preview = Preview::generate(&GeneratorConfig::Noise, 0, 0..=1, 1..=2);
+        let caves: usize = preview.chunks.iter().map(|chunk| chunk.caves).sum();
+        let solid: usize = preview.chunks.iter().map(|chunk| chunk.solid).sum();
+        let liquid: usize = preview.chunks.iter().map(|chunk| chunk.liquid).sum();
+        assert!(caves > 0 && solid > 0 && liquid > 0);
+        // flooded caves are caves too
+        assert_eq!(caves + solid, 4 * 128 * 128);
+
+        // the same every time
+        let again = Preview::generate(&GeneratorConfig::Noise, 0, 0..=1, 1..=2);
+        assert_eq!(again.chunks, preview.chunks);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::color::Color;
+use crate::material::Material;
+
+/// What the pixels of one color become when importing an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorRule {
+    pub color: Color,
+    pub material: Material,
+    /// Whether the cells fall (and bounce etc.), or are static walls
+    pub moving: bool,
+}
+
+/// Maps the colors of an imported image to materials. A pixel gets the rule
+/// with the closest color, so anti-aliased edges still map to something.
+/// Without rules (the default) a pixel becomes `Material::closest` to its
+/// color, with sand and water moving and the rest static.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColorMapping {
+    rules: Vec<ColorRule>,
+}
+
+impl ColorMapping {
+    pub fn new(rules: Vec<ColorRule>) -> Self {
+        assert!(!rules.is_empty(), "a color mapping needs at least one rule");
+        Self { rules }
+    }
+
+    pub fn rule_for(&self, color: Color) -> ColorRule {
+        match self
+            .rules
+            .iter()
+            .min_by_key(|rule| rule.color.distance_sqr(color))
+        {
+            Some(rule) => *rule,
+            None => {
+                let material = Material::closest(color);
+                ColorRule {
+                    color: material.color(1.0),
+                    material,
+                    moving: matches!(material, Material::Sand | Material::Water),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_mapping_closest_rule() {
+        let mapping = ColorMapping::new(vec![
+            ColorRule {
+                color: Color::rgb(0, 0, 0),
+                material: Material::Stone,
+                moving: false,
+            },
+            ColorRule {
+                color: Color::rgb(255, 255, 0),
+                material: Material::Sand,
+                moving: true,
+            },
+        ]);
+        assert_eq!(
+            mapping.rule_for(Color::rgb(20, 10, 0)).material,
+            Material::Stone
+        );
+        assert_eq!(
+            mapping.rule_for(Color::rgb(200, 220, 30)).material,
+            Material::Sand
+        );
+
+        let default = ColorMapping::default();
+        for material in Material::ALL {
+            assert_eq!(default.rule_for(material.color(1.0)).material, material);
+        }
+        assert!(default.rule_for(Material::Sand.color(1.0)).moving);
+        assert!(!default.rule_for(Material::Stone.color(1.0)).moving);
+    }
+}
@@ -0,0 +1,34 @@
+use rand::rand_core::impls;
+use rand::RngCore;
+
+/// The universe's random numbers (other than generation): a SplitMix64
+/// generator, small enough to save its state with the world so a restored
+/// world goes on drawing the same numbers.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WorldRng {
+    state: u64,
+}
+
+impl WorldRng {
+    pub fn new(seed: u64) -> WorldRng {
+        WorldRng { state: seed }
+    }
+}
+
+impl RngCore for WorldRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+}
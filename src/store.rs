@@ -0,0 +1,323 @@
+// Persistent storage for worlds.
+//
+// A `ChunkStore` holds serialized grids keyed by their `GridIndex`, plus one
+// blob of global ("meta") state. The universe streams grids in and out of its
+// store as the player moves, see `Universe::stream`.
+//
+// Implementations:
+// - **MemStore**: keeps everything in memory. Used by default, and (see
+//   `MemStore::staging`) by the web frontend, which passes grids in from
+//   IndexedDB as they're needed and moves saved ones out to it (see
+//   `take_grid`).
+// - **DirStore**: a world directory with one file per grid (named like the
+//   web frontend's IndexedDB keys without their world prefix,
+//   `grid_<x>_<y>`) plus a `meta.bin` file.
+//   Files are written to a temporary name and renamed into place, so a crash
+//   mid-save never leaves a truncated grid behind.
+// - **ArchiveStore**: the whole world in a single file, read on open and
+//   rewritten (atomically) on `flush`.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use fnv::{FnvHashMap, FnvHashSet};
+
+use crate::multigrid::GridIndex;
+
+const META_FILE: &str = "meta.bin";
+
+pub trait ChunkStore {
+    fn load_grid(&mut self, grid_index: GridIndex) -> io::Result<Option<Vec<u8>>>;
+
+    fn save_grid(&mut self, grid_index: GridIndex, bytes: &[u8]) -> io::Result<()>;
+
+    fn load_meta(&mut self) -> io::Result<Option<Vec<u8>>>;
+
+    fn save_meta(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Like `load_grid`, but the store may forget the grid afterwards, for
+    /// when the caller keeps it somewhere else from now on
+    fn take_grid(&mut self, grid_index: GridIndex) -> io::Result<Option<Vec<u8>>> {
+        self.load_grid(grid_index)
+    }
+
+    /// Tells the store that there's no stored copy of a grid elsewhere
+    /// either, see `MemStore::staging`
+    fn mark_absent(&mut self, _grid_index: GridIndex) {}
+
+    /// Makes all saves so far durable
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct MemStore {
+    meta: Option<Vec<u8>>,
+    grids: FnvHashMap<GridIndex, Vec<u8>>,
+    // Staging only: grids known not to be stored elsewhere either
+    #[serde(skip)]
+    staging: Option<FnvHashSet<GridIndex>>,
+}
+
+impl MemStore {
+    pub fn new() -> MemStore {
+        MemStore::default()
+    }
+
+    /// A store for grids kept elsewhere (like the web frontend's IndexedDB):
+    /// each grid is passed in with `save_grid` before it's needed, or
+    /// `mark_absent` if there's none, and forgotten once loaded. Loading a
+    /// grid the store hasn't been told about fails, so it isn't generated
+    /// over a stored one that is still on its way.
+    pub fn staging() -> MemStore {
+        MemStore {
+            staging: Some(FnvHashSet::default()),
+            ..MemStore::default()
+        }
+    }
+}
+
+impl ChunkStore for MemStore {
+    fn load_grid(&mut self, grid_index: GridIndex) -> io::Result<Option<Vec<u8>>> {
+        let Some(absent) = self.staging.as_mut() else {
+            return Ok(self.grids.get(&grid_index).cloned());
+        };
+        match self.grids.remove(&grid_index) {
+            Some(bytes) => Ok(Some(bytes)),
+            None if absent.remove(&grid_index) => Ok(None),
+            None => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("grid {grid_index:?} wasn't passed in yet"),
+            )),
+        }
+    }
+
+    fn save_grid(&mut self, grid_index: GridIndex, bytes: &[u8]) -> io::Result<()> {
+        if let Some(absent) = self.staging.as_mut() {
+            absent.remove(&grid_index);
+        }
+        self.grids.insert(grid_index, bytes.to_vec());
+        Ok(())
+    }
+
+    fn load_meta(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.meta.clone())
+    }
+
+    fn save_meta(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.meta = Some(bytes.to_vec());
+        Ok(())
+    }
+
+    fn take_grid(&mut self, grid_index: GridIndex) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.grids.remove(&grid_index))
+    }
+
+    fn mark_absent(&mut self, grid_index: GridIndex) {
+        if let Some(absent) = self.staging.as_mut() {
+            absent.insert(grid_index);
+        }
+    }
+}
+
+fn read_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, path)
+}
+
+pub struct DirStore {
+    path: PathBuf,
+}
+
+impl DirStore {
+    /// Opens (creating if needed) a world directory
+    pub fn open(path: impl AsRef<Path>) -> io::Result<DirStore> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+        Ok(DirStore { path })
+    }
+
+    fn grid_path(&self, grid_index: GridIndex) -> PathBuf {
+        self.path.join(format!(
+            "grid_{}_{}.bin",
+            grid_index.grid_offset.x, grid_index.grid_offset.y
+        ))
+    }
+}
+
+impl ChunkStore for DirStore {
+    fn load_grid(&mut self, grid_index: GridIndex) -> io::Result<Option<Vec<u8>>> {
+        read_file(&self.grid_path(grid_index))
+    }
+
+    fn save_grid(&mut self, grid_index: GridIndex, bytes: &[u8]) -> io::Result<()> {
+        write_file(&self.grid_path(grid_index), bytes)
+    }
+
+    fn load_meta(&mut self) -> io::Result<Option<Vec<u8>>> {
+        read_file(&self.path.join(META_FILE))
+    }
+
+    fn save_meta(&mut self, bytes: &[u8]) -> io::Result<()> {
+        write_file(&self.path.join(META_FILE), bytes)
+    }
+}
+
+pub struct ArchiveStore {
+    path: PathBuf,
+    contents: MemStore,
+    modified: bool,
+}
+
+impl ArchiveStore {
+    /// Opens an archive file, or starts an empty one if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> io::Result<ArchiveStore> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match read_file(&path)? {
+            Some(bytes) => bincode::deserialize(&bytes)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+            None => MemStore::new(),
+        };
+        Ok(ArchiveStore {
+            path,
+            contents,
+            modified: false,
+        })
+    }
+}
+
+impl ChunkStore for ArchiveStore {
+    fn load_grid(&mut self, grid_index: GridIndex) -> io::Result<Option<Vec<u8>>> {
+        self.contents.load_grid(grid_index)
+    }
+
+    fn save_grid(&mut self, grid_index: GridIndex, bytes: &[u8]) -> io::Result<()> {
+        self.modified = true;
+        self.contents.save_grid(grid_index, bytes)
+    }
+
+    fn load_meta(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.contents.load_meta()
+    }
+
+    fn save_meta(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.modified = true;
+        self.contents.save_meta(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.modified {
+            return Ok(());
+        }
+        let bytes = bincode::serialize(&self.contents)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        write_file(&self.path, &bytes)?;
+        self.modified = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v2::V2i;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rockies-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    // Exercises a store, reopening it (through `reopen`) to check durability
+    fn check_store<S: ChunkStore>(mut store: S, reopen: impl Fn(S) -> S) {
+        let grid_index = GridIndex {
+            grid_offset: V2i::new(-1, 2),
+        };
+        assert_eq!(store.load_grid(grid_index).unwrap(), None);
+        assert_eq!(store.load_meta().unwrap(), None);
+
+        store.save_grid(grid_index, &[1, 2, 3]).unwrap();
+        store.save_meta(&[9, 9]).unwrap();
+        store.flush().unwrap();
+
+        let mut store = reopen(store);
+        assert_eq!(store.load_grid(grid_index).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(store.load_meta().unwrap(), Some(vec![9, 9]));
+
+        store.save_grid(grid_index, &[4]).unwrap();
+        store.flush().unwrap();
+        let mut store = reopen(store);
+        assert_eq!(store.load_grid(grid_index).unwrap(), Some(vec![4]));
+    }
+
+    #[test]
+    fn test_mem_store() {
+        check_store(MemStore::new(), |store| store);
+    }
+
+    #[test]
+    fn test_mem_store_staging() {
+        let grid_index = |x| GridIndex {
+            grid_offset: V2i::new(x, 0),
+        };
+        let mut store = MemStore::staging();
+        // not passed in yet
+        let err = store.load_grid(grid_index(0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        // passed in grids are forgotten once loaded, like absent ones
+        store.save_grid(grid_index(0), &[0]).unwrap();
+        store.mark_absent(grid_index(1));
+        assert_eq!(store.load_grid(grid_index(0)).unwrap(), Some(vec![0]));
+        assert_eq!(store.load_grid(grid_index(1)).unwrap(), None);
+        assert!(store.load_grid(grid_index(0)).is_err());
+        assert!(store.load_grid(grid_index(1)).is_err());
+
+        // saved grids stay until taken out
+        store.save_grid(grid_index(2), &[2]).unwrap();
+        assert_eq!(store.take_grid(grid_index(2)).unwrap(), Some(vec![2]));
+        assert!(store.load_grid(grid_index(2)).is_err());
+    }
+
+    #[test]
+    fn test_dir_store() {
+        let path = temp_path("dir-store");
+        check_store(DirStore::open(&path).unwrap(), |_| {
+            DirStore::open(&path).unwrap()
+        });
+        assert!(path.join("grid_-1_2.bin").exists());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_archive_store() {
+        let path = temp_path("archive-store");
+        check_store(ArchiveStore::open(&path).unwrap(), |_| {
+            ArchiveStore::open(&path).unwrap()
+        });
+        assert!(path.is_file());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_archive_store_unflushed() {
+        let path = temp_path("archive-store-unflushed");
+        let mut store = ArchiveStore::open(&path).unwrap();
+        store.save_meta(&[1]).unwrap();
+        assert!(!path.exists());
+        store.flush().unwrap();
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+    }
+}
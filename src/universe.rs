@@ -1,44 +1,97 @@
 use std::cell::RefCell;
+use std::collections::BinaryHeap;
+use std::io::{Read, Write};
 use std::rc::Rc;
 
 use crate::assets;
 use crate::color::Color;
-use crate::generator::Generator;
+use crate::generator::{GeneratorConfig, WorldGenerator};
 use crate::grid::GridCellRef;
 use crate::inertia::Inertia;
+use crate::material::Material;
 use crate::multigrid::{CellIndex, GridIndex, MultiGrid, UniverseGrid};
+use crate::region::ColorMapping;
+#[cfg(test)]
+use crate::region::ColorRule;
+use crate::rng::WorldRng;
+use crate::store::{ChunkStore, MemStore};
 use crate::v2::{V2i, V2};
 
 use crate::log::log;
 
 use fnv::{FnvHashMap, FnvHashSet};
+use rand::Rng;
 use wasm_bindgen::prelude::*;
 
+/// Whether a cell is simulated. Collisions treat cells at rest as immovable,
+/// whatever their mass (which is always their material's density).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CellState {
+    Moving,
+    /// Came to rest, and starts moving again when a cell next to it is
+    /// removed or it's hit hard enough
+    Sleeping,
+    /// Terrain, stays put until it's broken: unstuck, or left without
+    /// support
+    Fixed,
+}
+
+impl CellState {
+    /// The state of a cell made at rest: fixed, but liquids sleep so that
+    /// they flow when dug out
+    pub fn at_rest(material: Material) -> CellState {
+        if material.is_liquid() {
+            CellState::Sleeping
+        } else {
+            CellState::Fixed
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Cell {
     pub index: CellIndex,
     pub color: Color,
     pub inertia: Inertia,
+    pub material: Material,
+    pub state: CellState,
 }
 
-pub const ELASTICITY: f64 = 0.2;
-
 impl Cell {
-    fn set_static(&mut self) {
+    fn sleep(&mut self) {
         self.inertia.velocity = V2::zero();
         self.inertia.pos = self.inertia.pos.round().to_v2();
-        self.inertia.mass = 0;
         self.inertia.collision_stats = 0;
+        self.state = CellState::Sleeping;
     }
-    fn unset_static(&mut self) {
-        self.inertia.mass = 1;
+    // Wakes a sleeping cell, or breaks a fixed one loose
+    fn wake(&mut self) {
         self.inertia.collision_stats = 0;
-        self.inertia.elasticity = ELASTICITY;
+        self.state = CellState::Moving;
+    }
+    pub fn is_moving(&self) -> bool {
+        self.state == CellState::Moving
+    }
+    // Liquid that isn't thrown or falling freely moves by flowing, see
+    // `UniverseCells::flow_liquids`
+    fn is_flowing(&self) -> bool {
+        self.material.is_liquid() && self.inertia.velocity == V2::zero()
+    }
+    // Cells at rest hold up the cells at rest around them, liquids don't
+    fn is_support(&self) -> bool {
+        !self.is_moving() && !self.material.is_liquid()
+    }
+    // Whether this cell sinks into `liquid` (if it's heavier) or floats up
+    // through it (if it's lighter), see `UniverseCells::flow_liquids`
+    fn displaces(&self, liquid: &Cell) -> bool {
+        liquid.material.is_liquid()
+            && !self.material.is_liquid()
+            && self.material.density() != liquid.material.density()
     }
 }
 
 #[wasm_bindgen]
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Stats {
     ticks: usize,
     cells_count: usize,
@@ -49,7 +102,7 @@ pub struct Stats {
 #[wasm_bindgen]
 impl Stats {
     pub fn get_and_reset(&mut self) -> Stats {
-        let res = self.clone();
+        let res = *self;
         *self = Stats::zero();
         res
     }
@@ -80,6 +133,7 @@ impl Stats {
     }
 }
 
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct Player {
     pub w: usize,
     pub h: usize,
@@ -102,7 +156,6 @@ impl Player {
                 force: V2::zero(),
                 pos: V2::new(x as f64, y as f64),
                 mass: 100,
-                elasticity: 0.0,
                 collision_stats: 0,
             },
 
@@ -152,12 +205,12 @@ impl Player {
 
     pub fn render(
         &self,
-        pixels: &mut Vec<u32>,
+        pixels: &mut [u32],
         offset: V2i,
         buf_width: usize,
         buf_height: usize,
         is_dig_mode: bool,
-    ) -> () {
+    ) {
         let hammy_0: (usize, usize, &[Color]) = assets::HAMMY_0;
         let hammy_1: (usize, usize, &[Color]) = assets::HAMMY_1;
         let hammy_2: (usize, usize, &[Color]) = assets::HAMMY_2;
@@ -210,16 +263,11 @@ impl Player {
                     y: new_player_pos.y + y as f64,
                 };
                 let posi = pos.round();
-                let grid = cells.grids.get(cells.grids.pos_to_index(posi)).unwrap();
-                if !grid.is_in_bounds(posi) {
-                    continue;
-                }
                 let player_part = Inertia {
-                    pos: pos,
+                    pos,
                     ..self.inertia
                 };
-                let get_res = grid.get(posi);
-                for cell_idx in get_res.neighbors {
+                for cell_idx in cells.grids.neighbors(posi) {
                     let cell = cell_idx.borrow();
                     let cell_inertia = &cell.inertia;
 
@@ -234,10 +282,10 @@ impl Player {
             }
         }
         //}
-        return Inertia {
+        Inertia {
             pos: new_player_pos,
             ..self.inertia
-        };
+        }
     }
 
     pub fn calc_forces(&mut self, gravity: V2) {
@@ -258,7 +306,7 @@ impl Player {
 fn clamp_velocity(v: V2) -> V2 {
     let max = V2 { x: 1.0, y: 1.0 };
     let min = V2 { x: -1.0, y: -1.0 };
-    return v.min(max).max(min);
+    v.min(max).max(min)
 }
 
 fn velocity_threshold(dt: f64) -> f64 {
@@ -269,45 +317,132 @@ pub struct UniverseCells {
     moving_cells: FnvHashMap<CellIndex, GridCellRef<Cell>>,
 
     grids: MultiGrid<Cell>,
-    generator: Generator,
+    store: Box<dyn ChunkStore>,
+    seed: u32,
+    generator_config: GeneratorConfig,
+    generator: Box<dyn WorldGenerator>,
+    // for everything random other than generation, derived from the seed too
+    // (and saved with the header)
+    rng: WorldRng,
     next_cell_index: usize,
 
     stats: Stats,
     // transient data:
     collisions_list: Vec<(GridCellRef<Cell>, GridCellRef<Cell>)>,
     collisions_map: FnvHashSet<(CellIndex, CellIndex)>,
+    // ticks each moving liquid cell has had nowhere to flow
+    liquid_idle_ticks: FnvHashMap<CellIndex, usize>,
+    // support checks too big to finish in one go, not saved
+    support_searches: Vec<SupportSearch>,
+}
+
+/// A flood fill through cells at rest, looking for the border of the loaded
+/// grids, see `UniverseCells::collapse_unsupported`
+struct SupportSearch {
+    // the cells reached, with the positions they were reached at
+    reached: Vec<(V2i, GridCellRef<Cell>)>,
+    // the ones not looked around yet, as (y, x, index into `reached`): the
+    // lowest first, ground tends to go down to the border
+    frontier: BinaryHeap<(i32, i32, usize)>,
+    visited: FnvHashSet<V2i>,
 }
 
 impl UniverseCells {
-    fn new(width: usize, height: usize) -> UniverseCells {
+    fn new(width: usize, height: usize, seed: u32) -> UniverseCells {
         UniverseCells {
             moving_cells: FnvHashMap::default(),
-            generator: Generator::new(0 as u32),
+            seed,
+            generator_config: GeneratorConfig::default(),
+            generator: GeneratorConfig::default().build(seed),
+            rng: WorldRng::new(seed as u64),
 
             grids: MultiGrid::new(width, height),
+            store: Box::new(MemStore::new()),
             next_cell_index: 0,
             stats: Stats::zero(),
 
             collisions_list: Vec::new(),
             collisions_map: FnvHashSet::default(),
+            liquid_idle_ticks: FnvHashMap::default(),
+            support_searches: Vec::new(),
         }
     }
 
-    pub fn ensure_grid(&mut self, grid_index: GridIndex) {
+    fn set_generator(&mut self, config: GeneratorConfig, seed: u32) {
+        self.generator = config.build(seed);
+        self.generator_config = config;
+        self.seed = seed;
+        self.rng = WorldRng::new(seed as u64);
+    }
+
+    /// Makes sure the grid is loaded: from the store if it was stored
+    /// before, otherwise by generating it. If the store fails, can't tell
+    /// yet (see `MemStore::staging`), or has a grid that can't be decoded
+    /// (say, of another version), the grid stays unloaded rather than being
+    /// generated over the stored one. Returns whether it's loaded.
+    pub fn ensure_grid(&mut self, grid_index: GridIndex) -> bool {
+        if self.grids.get(grid_index).is_some() {
+            return true;
+        }
         let width = self.grids.grid_width;
         let height = self.grids.grid_height;
+        match self.store.load_grid(grid_index) {
+            Ok(Some(bytes)) => match decode_grid(&bytes, grid_index, width, height) {
+                Ok(grid) => {
+                    self.load_from_storage(grid_index, grid);
+                    return true;
+                }
+                Err(err) => {
+                    log!("Failed to decode stored grid {grid_index:?}: {err}");
+                    return false;
+                }
+            },
+            Ok(None) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => return false,
+            Err(err) => {
+                log!("Failed to load grid {grid_index:?}: {err}");
+                return false;
+            }
+        }
         let generator = &mut self.generator;
         let (is_new, grid) = self
             .grids
             .or_insert_with(grid_index, || UniverseGrid::new(grid_index, width, height));
         if is_new {
-            generator.generate_pristine_grid(grid, grid_index, width, height)
+            generator.generate_pristine_grid(grid, grid_index, width, height);
+            grid.mark_pristine();
+            self.register_cells(grid_index);
         }
+        true
     }
 
     fn load_from_storage(&mut self, grid_index: GridIndex, grid: UniverseGrid<Cell>) {
         // Load a grid from storage, if it exists
         self.grids.insert(grid_index, grid);
+        self.register_cells(grid_index);
+    }
+
+    // Gives the cells of a newly loaded (or generated) grid fresh indices, so
+    // they can't clash with other cells' in `moving_cells` and
+    // `collisions_map`, and resumes the ones that were still moving
+    fn register_cells(&mut self, grid_index: GridIndex) {
+        let grid = self.grids.get(grid_index).unwrap();
+        let grid_origin = grid_index.to_pos(grid.width, grid.height);
+        for x in 0..grid.width {
+            for y in 0..grid.height {
+                let pos = V2i::new(x as i32, y as i32).plus(grid_origin);
+                for cell_ref in grid.get(pos).value {
+                    let mut cell = cell_ref.borrow_mut();
+                    self.next_cell_index += 1;
+                    cell.index = CellIndex {
+                        index: self.next_cell_index,
+                    };
+                    if cell.is_moving() {
+                        self.moving_cells.insert(cell.index, cell_ref.clone());
+                    }
+                }
+            }
+        }
     }
 
     pub fn get_range(
@@ -320,21 +455,23 @@ impl UniverseCells {
         let count = (end_pos.x - start_pos.x) * (end_pos.y - start_pos.y);
         let mut result = Vec::with_capacity(count as usize);
 
-        let mut cur_grid: Option<(GridIndex, &UniverseGrid<Cell>)> = Option::None;
+        let mut cur_grid: Option<(GridIndex, Option<&UniverseGrid<Cell>>)> = Option::None;
         for x in start_pos.x..end_pos.x {
             for y in start_pos.y..end_pos.y {
                 let pos = V2i::new(x, y);
                 let grid_index = self.grids.pos_to_index(pos);
 
                 // Only lookup grid if grid_index changed
-                if cur_grid.map_or(true, |x| x.0 != grid_index) {
-                    cur_grid = Some((grid_index, self.grids.get(grid_index).unwrap()));
+                if cur_grid.is_none_or(|x| x.0 != grid_index) {
+                    cur_grid = Some((grid_index, self.grids.get(grid_index)));
                 }
-                let grid = cur_grid.unwrap().1;
 
+                // grids that couldn't be loaded (yet) look empty
                 let mut cur_res = Vec::new();
-                for cell_ref in grid.get(pos).value.iter() {
-                    cur_res.push(cell_ref.clone());
+                if let Some(grid) = cur_grid.unwrap().1 {
+                    for cell_ref in grid.get(pos).value.iter() {
+                        cur_res.push(cell_ref.clone());
+                    }
                 }
                 result.push((pos, cur_res));
             }
@@ -344,14 +481,20 @@ impl UniverseCells {
     }
 
     fn ensure_grids(&mut self, start_pos: V2i, end_pos: V2i) {
-        // Pre-ensure all grids we'll need
-        let width = self.grids.grid_width;
-        let height = self.grids.grid_height;
-        for x in (start_pos.x..(end_pos.x + width as i32)).step_by(width) {
-            for y in (start_pos.y..(end_pos.y + height as i32)).step_by(height) {
-                let pos = V2i::new(x, y);
-                let grid_index = self.grids.pos_to_index(pos);
-                self.ensure_grid(grid_index);
+        // Pre-ensure all grids we'll need (`end_pos` is exclusive)
+        if end_pos.x <= start_pos.x || end_pos.y <= start_pos.y {
+            return;
+        }
+        let first = self.grids.pos_to_index(start_pos).grid_offset;
+        let last = self
+            .grids
+            .pos_to_index(end_pos.minus(V2i::new(1, 1)))
+            .grid_offset;
+        for x in first.x..=last.x {
+            for y in first.y..=last.y {
+                self.ensure_grid(GridIndex {
+                    grid_offset: V2i::new(x, y),
+                });
             }
         }
     }
@@ -359,7 +502,7 @@ impl UniverseCells {
     fn calc_forces(&mut self, gravity: V2) {
         for (_cell_idx, cell_ref) in self.moving_cells.iter() {
             let mut cell = cell_ref.borrow_mut();
-            if cell.inertia.mass > 0 {
+            if cell.is_moving() && !cell.is_flowing() {
                 cell.inertia.force = gravity.cmul(cell.inertia.mass as f64);
             }
         }
@@ -375,7 +518,7 @@ impl UniverseCells {
     fn update_velocity(&mut self, dt: f64) {
         for (_cell_idx, cell_ref) in self.moving_cells.iter() {
             let mut cell = cell_ref.borrow_mut();
-            if cell.inertia.mass > 0 {
+            if cell.is_moving() {
                 cell.inertia.velocity = clamp_velocity(
                     cell.inertia
                         .velocity
@@ -391,22 +534,16 @@ impl UniverseCells {
 
         for (_cell1_idx, cell1_ref) in self.moving_cells.iter() {
             let cell1 = cell1_ref.borrow();
-            let grid_index = self.grids.pos_to_index(cell1.inertia.pos.round());
-
-            if self.grids.get(grid_index).is_none() {
-                continue;
-            }
-            let get_res = self
-                .grids
-                .get(grid_index)
-                .unwrap()
-                .get(cell1.inertia.pos.round());
-            for cell2_ref in get_res.neighbors {
+            for cell2_ref in self.grids.neighbors(cell1.inertia.pos.round()) {
                 if Rc::ptr_eq(cell1_ref, cell2_ref) {
                     continue;
                 }
 
                 let cell2 = cell2_ref.borrow();
+                // cells at rest don't collide with each other
+                if !cell1.is_moving() && !cell2.is_moving() {
+                    continue;
+                }
                 if !self.collisions_map.insert((cell1.index, cell2.index)) {
                     continue;
                 }
@@ -436,37 +573,96 @@ impl UniverseCells {
             let mut cell1 = cell1_idx.borrow_mut();
             let mut cell2 = cell2_idx.borrow_mut();
 
+            // a hard hit wakes a sleeping cell, to take part in the collision
+            Self::wake_if_hit(&mut self.moving_cells, &mut cell1, cell1_idx, &cell2);
+            Self::wake_if_hit(&mut self.moving_cells, &mut cell2, cell2_idx, &cell1);
+
             let inertia2 = &cell2.inertia;
             let inertia1 = &cell1.inertia;
 
-            let mass1 = inertia1.mass;
-            let mass2 = inertia2.mass;
-            // static cell is involved, make them both static
-            if ((inertia1.mass == 0) || (inertia2.mass == 0))
+            // a cell at rest is involved, make them both sleep (liquids
+            // never do here, they start flowing instead). Not on liquid the
+            // other cell sinks or floats through, it doesn't hold it.
+            let holds = |cell: &Cell, other: &Cell| !cell.is_moving() && !other.displaces(cell);
+            if (holds(&cell1, &cell2) || holds(&cell2, &cell1))
                 && (low_velocity_collision(inertia1, inertia2, dt))
             {
-                if mass1 > 0 {
-                    cell1.set_static();
-                }
-                if mass2 > 0 {
-                    cell2.set_static();
+                for cell in [&mut cell1, &mut cell2] {
+                    if cell.material.is_liquid() {
+                        cell.inertia.velocity = V2::zero();
+                    } else if cell.is_moving() {
+                        let pos = cell.inertia.pos.round();
+                        let static_friction = cell.material.static_friction();
+                        match topple_side(&self.grids, &mut self.rng, pos, static_friction) {
+                            Some(side) => {
+                                cell.inertia.velocity = V2::new(side as f64 * TOPPLE_SPEED, 0.0)
+                            }
+                            None => cell.sleep(),
+                        }
+                    }
                 }
 
                 continue;
             }
 
-            let (new_inertia1, new_inertia2) = Inertia::collide(inertia1, inertia2);
+            let (mut new_inertia1, mut new_inertia2) = Inertia::collide(
+                inertia1,
+                cell1.material,
+                !cell1.is_moving(),
+                inertia2,
+                cell2.material,
+                !cell2.is_moving(),
+            );
+            // liquids splash: they don't bounce, they stay and start flowing
+            for (cell, new_inertia) in [(&cell1, &mut new_inertia1), (&cell2, &mut new_inertia2)] {
+                if cell.material.is_liquid() {
+                    *new_inertia = Inertia {
+                        velocity: V2::zero(),
+                        ..cell.inertia
+                    };
+                }
+            }
 
-            self.grids
-                .update_cell_pos(cell1_idx, inertia1.pos.round(), new_inertia1.pos.round());
-            self.grids
-                .update_cell_pos(cell2_idx, inertia2.pos.round(), new_inertia2.pos.round());
+            if !self.grids.update_cell_pos(
+                cell1_idx,
+                inertia1.pos.round(),
+                new_inertia1.pos.round(),
+            ) {
+                Self::freeze_at_border(&mut new_inertia1, inertia1.pos);
+            }
+            if !self.grids.update_cell_pos(
+                cell2_idx,
+                inertia2.pos.round(),
+                new_inertia2.pos.round(),
+            ) {
+                Self::freeze_at_border(&mut new_inertia2, inertia2.pos);
+            }
 
             Self::update_cell_collision(&mut cell1, new_inertia1);
             Self::update_cell_collision(&mut cell2, new_inertia2);
         }
     }
 
+    fn wake_if_hit(
+        moving_cells: &mut FnvHashMap<CellIndex, GridCellRef<Cell>>,
+        cell: &mut Cell,
+        cell_ref: &GridCellRef<Cell>,
+        hitter: &Cell,
+    ) {
+        let momentum = hitter.inertia.mass as f64 * hitter.inertia.velocity.magnitude();
+        if cell.state == CellState::Sleeping && momentum >= WAKE_IMPULSE {
+            cell.wake();
+            moving_cells.insert(cell.index, cell_ref.clone());
+        }
+    }
+
+    // A cell can't move into an unloaded grid: it stays (and stops) where it
+    // was, and keeps falling/moving once the grid is loaded.
+    fn freeze_at_border(inertia: &mut Inertia, old_pos: V2) {
+        inertia.pos = old_pos;
+        inertia.velocity = V2::zero();
+    }
+
     fn update_cell_collision(cell: &mut Cell, new_inertia: Inertia) {
         cell.inertia = new_inertia;
         cell.inertia.collision_stats += 1;
@@ -480,35 +676,44 @@ impl UniverseCells {
     fn update_pos(&mut self, dt: f64) {
         // update grid and positions
         let mut grids_to_update = Vec::new();
-        for (_cell_index, cell_ref) in &self.moving_cells {
+        for cell_ref in self.moving_cells.values() {
             let mut cell = cell_ref.borrow_mut();
+            if cell.is_flowing() {
+                continue;
+            }
             let old_pos = cell.inertia.pos;
             let new_pos = cell.inertia.pos.plus(cell.inertia.velocity.cmul(dt));
 
             let new_pos_i = new_pos.round();
-            // update grid:
-            self.grids
-                .update_cell_pos(&cell_ref, old_pos.round(), new_pos_i);
-            // update position:
-            cell.inertia.pos = new_pos;
+            // update grid and position:
+            if self
+                .grids
+                .update_cell_pos(cell_ref, old_pos.round(), new_pos_i)
+            {
+                cell.inertia.pos = new_pos;
+            } else {
+                Self::freeze_at_border(&mut cell.inertia, old_pos);
+            }
 
-            grids_to_update.push((self.grids.pos_to_index(new_pos_i), new_pos_i));
+            let pos_i = cell.inertia.pos.round();
+            grids_to_update.push((self.grids.pos_to_index(pos_i), pos_i));
         }
 
-        for (grid, pos) in grids_to_update {
+        for (_grid, _pos) in grids_to_update {
             //  self.correct_positions(grid, pos, dt);
         }
 
-        // Filter out moving cells that have been made static
-        self.moving_cells.retain(|_, cell_ref| {
-            let cell = cell_ref.borrow();
-            cell.inertia.mass > 0
-        });
+        // Filter out moving cells that have come to rest
+        self.moving_cells
+            .retain(|_, cell_ref| cell_ref.borrow().is_moving());
     }
 
+    #[allow(dead_code)]
     fn correct_positions(&mut self, grid_index: GridIndex, pos: V2i, dt: f64) {
         // Apply position correction to prevent overlaps
-        self.ensure_grid(grid_index);
+        if !self.ensure_grid(grid_index) {
+            return;
+        }
         // ensure all surrounding grids are loaded
         self.ensure_grids(pos.plus(V2i::new(-1, -1)), pos.plus(V2i::new(1, 1)));
 
@@ -558,8 +763,8 @@ impl UniverseCells {
             let mut cell = cell_ref.borrow_mut();
             cell.inertia.pos = new_pos.to_v2();
             if cell.inertia.velocity.magnitude_sqr() < velocity_threshold(dt) {
-                // If the cell is moving slowly, make it static
-                cell.set_static();
+                // If the cell is moving slowly, let it sleep
+                cell.sleep();
             } else {
                 self.moving_cells.insert(cell.index, cell_ref.clone());
             }
@@ -570,11 +775,17 @@ impl UniverseCells {
         let pos = cell.inertia.pos.round();
         let grid_index = self.grids.pos_to_index(pos);
         // don't allow adding too many cells in the same region
-        let grid = self.grids.get_mut(grid_index).unwrap();
-        let get_res = grid.get(pos);
-        if get_res.neighbors.len() > 6 {
+        if self.grids.neighbors(pos).count() > 6 {
             return;
         }
+        let cell_ref = self.insert_cell(grid_index, pos, cell);
+        let index = cell_ref.borrow().index;
+        self.moving_cells.insert(index, cell_ref);
+    }
+
+    // Puts a cell into a loaded grid with a fresh index
+    fn insert_cell(&mut self, grid_index: GridIndex, pos: V2i, cell: Cell) -> GridCellRef<Cell> {
+        let grid = self.grids.get_mut(grid_index).unwrap();
 
         self.next_cell_index += 1;
         let index = CellIndex {
@@ -584,8 +795,64 @@ impl UniverseCells {
 
         self.stats.cells_count += 1;
         let cell_ref = Rc::new(RefCell::new(cell));
-        self.moving_cells.insert(index, cell_ref.clone());
         grid.put(pos, cell_ref.clone());
+        cell_ref
+    }
+
+    /// Draws a PNG into the world with its top left corner at `origin`: each
+    /// non-transparent pixel replaces whatever is at its position with a cell
+    /// made by `mapping` (keeping the pixel's color). Grids are loaded or
+    /// generated as needed, pixels in grids that can't be are skipped.
+    /// Returns the number of cells created.
+    pub fn import_image(
+        &mut self,
+        png: &[u8],
+        origin: V2i,
+        mapping: &ColorMapping,
+    ) -> Result<usize, image::ImageError> {
+        let img = image::load_from_memory(png)?.to_rgba8();
+        let mut count = 0;
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            if a == 0 {
+                continue;
+            }
+            let pos = origin.plus(V2i::new(x as i32, y as i32));
+            let color = Color { r, g, b };
+            let rule = mapping.rule_for(color);
+
+            if !self.ensure_grid(self.grids.pos_to_index(pos)) {
+                continue;
+            }
+            self.clear_pos(pos);
+            let cell_ref = self.insert_cell(
+                self.grids.pos_to_index(pos),
+                pos,
+                Cell {
+                    index: CellIndex::default(),
+                    color,
+                    inertia: Inertia {
+                        velocity: V2::zero(),
+                        force: V2::zero(),
+                        pos: pos.to_v2(),
+                        mass: rule.material.density(),
+                        collision_stats: 0,
+                    },
+                    material: rule.material,
+                    state: if rule.moving {
+                        CellState::Moving
+                    } else {
+                        CellState::at_rest(rule.material)
+                    },
+                },
+            );
+            if rule.moving {
+                let index = cell_ref.borrow().index;
+                self.moving_cells.insert(index, cell_ref);
+            }
+            count += 1;
+        }
+        Ok(count)
     }
 
     fn get_cells(&mut self, center: V2i, radius: usize) -> Vec<GridCellRef<Cell>> {
@@ -595,7 +862,9 @@ impl UniverseCells {
             for j in -r..r {
                 let ppos = center.plus(V2i::new(i, j));
                 let grid_index = self.grids.pos_to_index(ppos);
-                self.ensure_grid(grid_index);
+                if !self.ensure_grid(grid_index) {
+                    continue;
+                }
                 let get_res = self.grids.get(grid_index).unwrap().get(ppos);
                 res.extend_from_slice(get_res.neighbors);
             }
@@ -606,7 +875,7 @@ impl UniverseCells {
     pub fn unstick_cells(&mut self, center: V2i, radius: usize) {
         for cell_idx in self.get_cells(center, radius) {
             let cell = cell_idx.borrow_mut();
-            if cell.inertia.mass > 0 {
+            if cell.is_moving() {
                 continue;
             }
             self.moving_cells.insert(cell.index, cell_idx.clone());
@@ -615,34 +884,360 @@ impl UniverseCells {
     }
 
     fn unstick_one_cell(&mut self, mut cell: std::cell::RefMut<'_, Cell>) {
-        cell.unset_static();
+        cell.wake();
+        let kick = self.rng.random_range(-5..5) as f64;
         cell.inertia.velocity = V2 {
-            x: 2.0 * ((cell.index.index as i32) % 10 - 5) as f64 / 10.0,
-            y: -1.0 * ((cell.index.index as i32) % 10 - 5) as f64 / 10.0,
+            x: 2.0 * kick / 10.0,
+            y: -kick / 10.0,
         };
     }
 
     pub fn remove_cell(&mut self, ppos: V2i) {
+        let removed = self.clear_pos(ppos);
+        self.wake_neighbors(ppos);
+        if removed {
+            self.collapse_unsupported(&[ppos]);
+        }
+    }
+
+    // Removes the cells at a position (loading its grid), returns whether
+    // there were any
+    fn clear_pos(&mut self, ppos: V2i) -> bool {
         let grid_index = self.grids.pos_to_index(ppos);
-        self.ensure_grid(grid_index);
+        if !self.ensure_grid(grid_index) {
+            return false;
+        }
 
-        let values: Vec<GridCellRef<Cell>> = self
-            .grids
-            .get(grid_index)
+        let values: Vec<GridCellRef<Cell>> =
+            self.grids.get(grid_index).unwrap().get(ppos).value.to_vec();
+        let removed = !values.is_empty();
+
+        for cell_ref in values {
+            self.remove_cell_ref(ppos, &cell_ref);
+        }
+        removed
+    }
+
+    // Removes one cell at a position in a loaded grid
+    fn remove_cell_ref(&mut self, pos: V2i, cell_ref: &GridCellRef<Cell>) {
+        let cell = cell_ref.borrow();
+        self.moving_cells.remove(&cell.index);
+        self.liquid_idle_ticks.remove(&cell.index);
+        let grid_index = self.grids.pos_to_index(pos);
+        self.grids
+            .get_mut(grid_index)
             .unwrap()
-            .get(ppos)
-            .value
+            .remove(pos, cell_ref);
+    }
+
+    /// Lets the cells at rest around `positions` fall if nothing holds them
+    /// up any more, even fixed ones. A structure of cells at rest is held up
+    /// if it reaches the border of the loaded grids: the world goes on there,
+    /// down to its floor. Otherwise the whole of it starts moving. Big
+    /// structures are searched at most `SUPPORT_SEARCH_LIMIT` cells per tick,
+    /// see `continue_support_searches`.
+    fn collapse_unsupported(&mut self, positions: &[V2i]) {
+        let starts: Vec<GridCellRef<Cell>> = positions
             .iter()
-            .map(|x| x.clone())
+            .flat_map(|pos| self.grids.neighbors(*pos))
+            .filter(|cell_ref| cell_ref.borrow().is_support())
+            .cloned()
             .collect();
+        let mut searched = FnvHashSet::default();
+        for cell_ref in starts {
+            let start = cell_ref.borrow().inertia.pos.round();
+            if searched.contains(&start) {
+                continue;
+            }
+            let mut search = SupportSearch {
+                reached: vec![(start, cell_ref)],
+                frontier: BinaryHeap::from([(start.y, start.x, 0)]),
+                visited: FnvHashSet::from_iter([start]),
+            };
+            let held = self.advance_support_search(&mut search);
+            searched.extend(search.visited.iter().copied());
+            match held {
+                Some(true) => {}
+                Some(false) => self.collapse(&search),
+                None => self.support_searches.push(search),
+            }
+        }
+    }
 
-        for cell_ref in values {
-            let cell = cell_ref.borrow();
-            self.moving_cells.remove(&cell.index);
-            self.grids
-                .get_mut(grid_index)
-                .unwrap()
-                .remove(ppos, &cell_ref.clone());
+    /// Carries on with the support checks left unfinished, see
+    /// `collapse_unsupported`
+    fn continue_support_searches(&mut self) {
+        for mut search in std::mem::take(&mut self.support_searches) {
+            match self.advance_support_search(&mut search) {
+                Some(true) => {}
+                Some(false) => self.collapse(&search),
+                None => self.support_searches.push(search),
+            }
+        }
+    }
+
+    // Looks around up to `SUPPORT_SEARCH_LIMIT` more cells of `search`:
+    // whether it's held up, or None if there are more to look at. Cells
+    // that moved or went away since they were reached (on an earlier tick)
+    // aren't looked around.
+    fn advance_support_search(&self, search: &mut SupportSearch) -> Option<bool> {
+        for _ in 0..SUPPORT_SEARCH_LIMIT {
+            let Some((_, _, index)) = search.frontier.pop() else {
+                return Some(false);
+            };
+            let (pos, cell_ref) = &search.reached[index];
+            let pos = *pos;
+            if !self.is_support_at(pos, cell_ref) {
+                continue;
+            }
+            if !self.is_loaded_around(pos) {
+                return Some(true);
+            }
+            for cell_ref in self.grids.neighbors(pos) {
+                let cell = cell_ref.borrow();
+                if !cell.is_support() {
+                    continue;
+                }
+                let cell_pos = cell.inertia.pos.round();
+                if search.visited.insert(cell_pos) {
+                    search
+                        .frontier
+                        .push((cell_pos.y, cell_pos.x, search.reached.len()));
+                    search.reached.push((cell_pos, cell_ref.clone()));
+                }
+            }
+        }
+        None
+    }
+
+    // Whether `cell_ref` is still at rest at `pos`, where a support check
+    // reached it
+    fn is_support_at(&self, pos: V2i, cell_ref: &GridCellRef<Cell>) -> bool {
+        cell_ref.borrow().is_support()
+            && self
+                .grids
+                .get(self.grids.pos_to_index(pos))
+                .is_some_and(|grid| {
+                    grid.get(pos)
+                        .value
+                        .iter()
+                        .any(|other| Rc::ptr_eq(other, cell_ref))
+                })
+    }
+
+    // Sets the cells at rest a support check reached moving, the ones still
+    // where it reached them
+    fn collapse(&mut self, search: &SupportSearch) {
+        for (pos, cell_ref) in search.reached.iter() {
+            if !self.is_support_at(*pos, cell_ref) {
+                continue;
+            }
+            let mut cell = cell_ref.borrow_mut();
+            cell.wake();
+            self.moving_cells.insert(cell.index, cell_ref.clone());
+        }
+    }
+
+    fn is_loaded(&self, pos: V2i) -> bool {
+        self.grids.get(self.grids.pos_to_index(pos)).is_some()
+    }
+
+    // Whether the grids of `pos` and all its neighbors are loaded
+    fn is_loaded_around(&self, pos: V2i) -> bool {
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter().all(|(x, y)| {
+            let corner = pos.plus(V2i::new(*x, *y));
+            self.grids.get(self.grids.pos_to_index(corner)).is_some()
+        })
+    }
+
+    /// Blows up the cells around `center`: the ones within
+    /// `EXPLOSION_CORE * radius` are destroyed, down to half of that the
+    /// harder their material is (see `Material::hardness`), the rest within
+    /// `radius` start moving away from the center (fixed ones too), faster
+    /// the closer they are, see `blast_velocity`. Whatever the blast leaves
+    /// without support caves in. Grids that aren't loaded are left alone.
+    pub fn explode(&mut self, center: V2, radius: f64, strength: f64) {
+        let reach = radius.ceil() as i32 + 1;
+        let center_i = center.round();
+
+        let mut edge = Vec::new();
+        for x in -reach..=reach {
+            for y in -reach..=reach {
+                let pos = center_i.plus(V2i::new(x, y));
+                if !self.is_loaded(pos) {
+                    continue;
+                }
+                let distance = pos.to_v2().minus(center).magnitude();
+                if distance > radius {
+                    if distance <= radius + 1.0 {
+                        edge.push(pos);
+                    }
+                    continue;
+                }
+                let grid = self.grids.get(self.grids.pos_to_index(pos)).unwrap();
+                for cell_ref in grid.get(pos).value.to_vec() {
+                    let hardness = cell_ref.borrow().material.hardness();
+                    let core = EXPLOSION_CORE * radius * (1.0 - hardness / 2.0);
+                    if distance <= core {
+                        self.remove_cell_ref(pos, &cell_ref);
+                        continue;
+                    }
+                    let mut cell = cell_ref.borrow_mut();
+                    if !cell.is_moving() {
+                        cell.wake();
+                        self.moving_cells.insert(cell.index, cell_ref.clone());
+                    }
+                    let blast = blast_velocity(center, cell.inertia.pos, radius, strength);
+                    cell.inertia.velocity = cell.inertia.velocity.plus(blast);
+                }
+            }
+        }
+        for pos in edge.iter() {
+            self.wake_neighbors(*pos);
+        }
+        self.collapse_unsupported(&edge);
+    }
+
+    // Whether something flying at `inertia` hits a cell
+    fn is_hit(&self, inertia: &Inertia) -> bool {
+        self.grids
+            .neighbors(inertia.pos.round())
+            .any(|cell_ref| Inertia::is_collision(inertia, &cell_ref.borrow().inertia))
+    }
+
+    /// Moves liquid cells a step, like a cellular automaton: down if they
+    /// can, else diagonally down, else sideways if there's liquid on top or
+    /// on the other side pushing them. So liquids spread out until their
+    /// surface is level.
+    /// Liquid that has nowhere to go for a while goes to sleep, and starts
+    /// flowing again when a cell next to it is vacated.
+    /// Other moving cells trade places with the liquid below them if they're
+    /// heavier, or above them if they're lighter, so they sink or float up.
+    fn flow_liquids(&mut self) {
+        let displacing: Vec<GridCellRef<Cell>> = self
+            .moving_cells
+            .values()
+            .filter(|cell_ref| !cell_ref.borrow().material.is_liquid())
+            .cloned()
+            .collect();
+        for cell_ref in displacing {
+            let (pos, density) = {
+                let cell = cell_ref.borrow();
+                (cell.inertia.pos.round(), cell.material.density())
+            };
+            let liquid = [V2i::new(0, 1), V2i::new(0, -1)]
+                .iter()
+                .filter_map(|offset| self.liquid_at(pos.plus(*offset)))
+                .find(|liquid_ref| {
+                    let liquid = liquid_ref.borrow();
+                    let below = liquid.inertia.pos.round().y > pos.y;
+                    cell_ref.borrow().displaces(&liquid)
+                        && (density > liquid.material.density()) == below
+                });
+            let Some(liquid_ref) = liquid else {
+                continue;
+            };
+            let target = liquid_ref.borrow().inertia.pos.round();
+            // both moves below have to happen, or neither
+            if !self.is_loaded(pos) || !self.is_loaded(target) {
+                continue;
+            }
+            self.grids.update_cell_pos(&cell_ref, pos, target);
+            self.grids.update_cell_pos(&liquid_ref, target, pos);
+            cell_ref.borrow_mut().inertia.pos = target.to_v2();
+            let mut liquid = liquid_ref.borrow_mut();
+            liquid.inertia.pos = pos.to_v2();
+            liquid.inertia.velocity = V2::zero();
+            if !liquid.is_moving() {
+                liquid.wake();
+                self.moving_cells.insert(liquid.index, liquid_ref.clone());
+            }
+            self.liquid_idle_ticks.remove(&liquid.index);
+        }
+
+        let liquids: Vec<GridCellRef<Cell>> = self
+            .moving_cells
+            .values()
+            .filter(|cell_ref| cell_ref.borrow().is_flowing())
+            .cloned()
+            .collect();
+        for cell_ref in liquids {
+            let pos = cell_ref.borrow().inertia.pos.round();
+            let side = if self.rng.random_bool(0.5) { 1 } else { -1 };
+            // sideways only with liquid on top or behind pushing, so that
+            // lone drops and films come to rest
+            let pushed = self.is_liquid_at(pos.plus(V2i::new(0, -1)));
+            let pushed_to = |side: i32| pushed || self.is_liquid_at(pos.plus(V2i::new(-side, 0)));
+            let moves = [
+                (V2i::new(0, 1), true),
+                (V2i::new(side, 1), true),
+                (V2i::new(-side, 1), true),
+                (V2i::new(side, 0), pushed_to(side)),
+                (V2i::new(-side, 0), pushed_to(-side)),
+            ];
+            let target = moves
+                .iter()
+                .filter(|(_, allowed)| *allowed)
+                .map(|(offset, _)| pos.plus(*offset))
+                .find(|target| self.is_empty_at(*target));
+
+            let index = cell_ref.borrow().index;
+            match target {
+                Some(target) if self.grids.update_cell_pos(&cell_ref, pos, target) => {
+                    let mut cell = cell_ref.borrow_mut();
+                    cell.inertia.pos = target.to_v2();
+                    cell.inertia.velocity = V2::zero();
+                    drop(cell);
+                    self.liquid_idle_ticks.remove(&index);
+                    self.wake_neighbors(pos);
+                }
+                _ => {
+                    let idle = self.liquid_idle_ticks.entry(index).or_insert(0);
+                    *idle += 1;
+                    if *idle > LIQUID_REST_TICKS {
+                        self.liquid_idle_ticks.remove(&index);
+                        self.moving_cells.remove(&index);
+                        cell_ref.borrow_mut().sleep();
+                    }
+                }
+            }
+        }
+    }
+
+    // Nothing at `pos`, in a loaded grid
+    fn is_empty_at(&self, pos: V2i) -> bool {
+        self.grids
+            .get(self.grids.pos_to_index(pos))
+            .is_some_and(|grid| grid.get(pos).value.is_empty())
+    }
+
+    fn is_liquid_at(&self, pos: V2i) -> bool {
+        self.liquid_at(pos).is_some()
+    }
+
+    // A liquid cell at `pos`, in a loaded grid
+    fn liquid_at(&self, pos: V2i) -> Option<GridCellRef<Cell>> {
+        let grid = self.grids.get(self.grids.pos_to_index(pos))?;
+        grid.get(pos)
+            .value
+            .iter()
+            .find(|cell_ref| cell_ref.borrow().material.is_liquid())
+            .cloned()
+    }
+
+    // Wakes the sleeping cells around a vacated `pos`, to fall or flow into
+    // it. Fixed cells stay.
+    fn wake_neighbors(&mut self, pos: V2i) {
+        let sleeping: Vec<GridCellRef<Cell>> = self
+            .grids
+            .neighbors(pos)
+            .filter(|cell_ref| cell_ref.borrow().state == CellState::Sleeping)
+            .cloned()
+            .collect();
+        for cell_ref in sleeping {
+            let mut cell = cell_ref.borrow_mut();
+            cell.wake();
+            self.moving_cells.insert(cell.index, cell_ref.clone());
         }
     }
 
@@ -660,15 +1255,25 @@ impl UniverseCells {
         self.grids.get_far_grids(center.round(), drop_radius)
     }
 
-    fn save_grid(&mut self, grid_index: GridIndex) -> Option<&UniverseGrid<Cell>> {
-        self.grids.get(grid_index)
+    fn get_dirty_grids(&self) -> Vec<GridIndex> {
+        self.grids.get_dirty_grids()
+    }
+
+    // Stores a grid, marking it as unmodified
+    fn store_grid(&mut self, grid_index: GridIndex) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(grid) = self.grids.get(grid_index) {
+            self.store.save_grid(grid_index, &encode_grid(grid)?)?;
+            self.grids.mark_saved(grid_index);
+        }
+        Ok(())
     }
 
+    // Dirty grids are never dropped, see `MultiGrid::drop_grid`
     pub fn drop_grid(&mut self, grid_index: GridIndex) {
         let maybe_grid = self.grids.get_mut(grid_index);
         let grid = match maybe_grid {
-            Some(grid) => grid,
-            None => {
+            Some(grid) if !grid.is_dirty() => grid,
+            _ => {
                 return;
             }
         };
@@ -681,6 +1286,7 @@ impl UniverseCells {
                 for cell_index in values {
                     //self.cells.remove(&cell_index);
                     self.moving_cells.remove(&cell_index.borrow().index);
+                    self.liquid_idle_ticks.remove(&cell_index.borrow().index);
                 }
             }
         }
@@ -688,17 +1294,153 @@ impl UniverseCells {
     }
 }
 
+// Ticks a liquid cell with nowhere to flow stays moving before it rests
+const LIQUID_REST_TICKS: usize = 10;
+
+// Cells at rest a support check looks at per tick, before leaving the rest
+// for the next one
+const SUPPORT_SEARCH_LIMIT: usize = 1024;
+
+// Part of an explosion's radius in which cells are destroyed
+const EXPLOSION_CORE: f64 = 0.4;
+
+// Radius and strength of a thrown bomb's explosion
+const BOMB_RADIUS: f64 = 8.0;
+const BOMB_STRENGTH: f64 = 1.0;
+
+/// The velocity an explosion at `center` gives to something at `pos`: away
+/// from the center, `strength` at the center falling off linearly to zero
+/// at `radius`
+fn blast_velocity(center: V2, pos: V2, radius: f64, strength: f64) -> V2 {
+    let offset = pos.minus(center);
+    let distance = offset.magnitude();
+    if distance >= radius {
+        return V2::zero();
+    }
+    // straight up when right at the center
+    let direction = if distance > 0.0 {
+        offset.cdiv(distance)
+    } else {
+        V2::new(0.0, -1.0)
+    };
+    direction.cmul(strength * (1.0 - distance / radius))
+}
+
+// How many columns to each side a cell coming to rest looks at for a drop
+// too steep to rest next to, and how fast it slides off to there
+const REPOSE_SEARCH: i32 = 8;
+const TOPPLE_SPEED: f64 = 0.5;
+
+/// The side (-1 or 1) a cell coming to rest at `pos` topples over to, if
+/// any: it rests on a pile up to `atan(static_friction)` steep, so it keeps
+/// going if the surface beside it drops away faster than that within
+/// `REPOSE_SEARCH` columns.
+fn topple_side(
+    grids: &MultiGrid<Cell>,
+    rng: &mut WorldRng,
+    pos: V2i,
+    static_friction: f64,
+) -> Option<i32> {
+    // nothing there, in a loaded grid
+    let is_empty = |pos: V2i| {
+        grids
+            .get(grids.pos_to_index(pos))
+            .is_some_and(|grid| grid.get(pos).value.is_empty())
+    };
+    let first = if rng.random_bool(0.5) { 1 } else { -1 };
+    [first, -first].into_iter().find(|side| {
+        (1..=REPOSE_SEARCH)
+            .map(|distance| (distance, pos.plus(V2i::new(side * distance, 0))))
+            .take_while(|(_, beside)| is_empty(*beside))
+            .any(|(distance, beside)| {
+                let drop = (1..=2 * distance)
+                    .take_while(|depth| is_empty(beside.plus(V2i::new(0, *depth))))
+                    .count();
+                drop as f64 > static_friction * distance as f64
+            })
+    })
+}
+
+// Momentum a moving cell needs to wake a sleeping cell it hits
+const WAKE_IMPULSE: f64 = 2.0;
+
 fn low_velocity_collision(inertia1: &Inertia, inertia2: &Inertia, dt: f64) -> bool {
     (inertia1.velocity.magnitude_sqr() < velocity_threshold(dt))
         && (inertia2.velocity.magnitude_sqr() < velocity_threshold(dt))
 }
 
+// Written before the world header, bump when the format changes
+const WORLD_VERSION: u32 = 7;
+
+// Global (not per-grid) state of the world, saved alongside its grids
+#[derive(serde::Serialize, serde::Deserialize)]
+struct WorldHeader<P> {
+    grid_width: usize,
+    grid_height: usize,
+    gravity: V2,
+    dt: f64,
+    next_cell_index: usize,
+    seed: u32,
+    generator: GeneratorConfig,
+    rng: WorldRng,
+    stats: Stats,
+    player: P,
+    bombs: Vec<Bomb>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct UniverseSerialData<P> {
+    header: WorldHeader<P>,
+    grids: Vec<(GridIndex, Vec<u8>)>, // (index, serialized grid)
+}
+
+fn write_version(writer: &mut impl Write) -> bincode::Result<()> {
+    bincode::serialize_into(writer, &WORLD_VERSION)
+}
+
+fn read_version(reader: &mut impl Read) -> bincode::Result<()> {
+    let version: u32 = bincode::deserialize_from(reader)?;
+    if version != WORLD_VERSION {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "unsupported world version {version}, expected {WORLD_VERSION}"
+        ))));
+    }
+    Ok(())
+}
+
+// A grid as stored, tagged with the version like the header
+fn encode_grid(grid: &UniverseGrid<Cell>) -> bincode::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    write_version(&mut bytes)?;
+    bytes.extend(grid.to_vec()?);
+    Ok(bytes)
+}
+
+fn decode_grid(
+    mut bytes: &[u8],
+    grid_index: GridIndex,
+    width: usize,
+    height: usize,
+) -> bincode::Result<UniverseGrid<Cell>> {
+    read_version(&mut bytes)?;
+    UniverseGrid::from_slice(bytes, grid_index, width, height)
+}
+
+/// Thrown by the player, flies until it hits something and explodes there,
+/// see `Universe::explode`
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Bomb {
+    pub inertia: Inertia,
+}
+
 pub struct Universe {
     gravity: V2,
     dt: f64,
     pub cells: UniverseCells,
 
     pub player: Player,
+    // in flight, not saved
+    bombs: Vec<Bomb>,
 }
 
 impl Universe {
@@ -717,16 +1459,66 @@ impl Universe {
         self.player.update_velocity(self.dt);
     }
 
-    pub fn save_grid(&mut self, grid_index: GridIndex) -> Option<JsValue> {
-        self.cells
-            .save_grid(grid_index)
-            .map(|grid| grid.to_bytes().unwrap())
-    }
-
     pub fn drop_grid(&mut self, grid_index: GridIndex) {
         self.cells.drop_grid(grid_index)
     }
 
+    /// Blows up the cells around `center` (see `UniverseCells::explode`),
+    /// and pushes the player away if it's within `radius`
+    pub fn explode(&mut self, center: V2, radius: f64, strength: f64) {
+        self.cells.explode(center, radius, strength);
+        let player_center = self.player.inertia.pos.plus(V2::new(
+            self.player.w as f64 / 2.0,
+            self.player.h as f64 / 2.0,
+        ));
+        let blast = blast_velocity(center, player_center, radius, strength);
+        self.player.inertia.velocity = self.player.inertia.velocity.plus(blast);
+    }
+
+    pub fn throw_bomb(&mut self, pos: V2, velocity: V2) {
+        self.bombs.push(Bomb {
+            inertia: Inertia {
+                velocity,
+                force: V2::zero(),
+                pos,
+                mass: 1,
+                collision_stats: 0,
+            },
+        });
+    }
+
+    pub fn bombs(&self) -> &[Bomb] {
+        &self.bombs
+    }
+
+    // Moves the bombs a step, exploding the ones that hit something. Like
+    // cells, bombs can't move into unloaded grids: they wait at the border,
+    // without gaining speed.
+    fn update_bombs(&mut self) {
+        let (gravity, dt) = (self.gravity, self.dt);
+        let cells = &self.cells;
+        let mut centers = Vec::new();
+        self.bombs.retain_mut(|bomb| {
+            let inertia = &mut bomb.inertia;
+            let velocity = inertia.velocity.plus(gravity.cmul(dt));
+            let pos = inertia.pos.plus(velocity.cmul(dt));
+            if !cells.is_loaded(pos.round()) {
+                UniverseCells::freeze_at_border(inertia, inertia.pos);
+                return true;
+            }
+            inertia.velocity = velocity;
+            inertia.pos = pos;
+            let hit = cells.is_hit(inertia);
+            if hit {
+                centers.push(inertia.pos);
+            }
+            !hit
+        });
+        for center in centers {
+            self.explode(center, BOMB_RADIUS, BOMB_STRENGTH);
+        }
+    }
+
     pub fn get_missing_grids(&self) -> Vec<GridIndex> {
         self.cells.get_missing_grids(self.player.inertia.pos)
     }
@@ -739,21 +1531,140 @@ impl Universe {
         self.cells.get_droppable_grids(self.player.inertia.pos)
     }
 
-    pub fn load_from_storage(
+    pub fn get_dirty_grids(&self) -> Vec<GridIndex> {
+        self.cells.get_dirty_grids()
+    }
+
+    /// Replaces the store, restoring the world header (player etc.) from it
+    /// if it has one. Should be called before any grid is loaded.
+    pub fn set_store(
         &mut self,
-        grid_index: GridIndex,
-        bytes: JsValue,
+        mut store: Box<dyn ChunkStore>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let grid = UniverseGrid::from_bytes(
-            bytes,
-            grid_index,
-            self.cells.grids.grid_width,
-            self.cells.grids.grid_height,
-        )?;
-        self.cells.load_from_storage(grid_index, grid);
+        if let Some(bytes) = store.load_meta()? {
+            self.read_header(bytes.as_slice())?;
+        }
+        self.cells.store = store;
+        Ok(())
+    }
+
+    pub fn store(&mut self) -> &mut dyn ChunkStore {
+        self.cells.store.as_mut()
+    }
+
+    /// Streams grids around the player in and out of the store: loads (or
+    /// generates) missing grids, stores the dirty grids and the world header,
+    /// and drops the grids that are far away. Pristine grids are dropped
+    /// without ever being stored.
+    ///
+    /// Returns the indices of the grids that were stored.
+    pub fn stream(&mut self) -> Result<Vec<GridIndex>, Box<dyn std::error::Error>> {
+        for grid_index in self.get_missing_grids() {
+            self.cells.ensure_grid(grid_index);
+        }
+
+        let saved = self.get_dirty_grids();
+        for grid_index in saved.iter() {
+            self.cells.store_grid(*grid_index)?;
+        }
+        let mut header = Vec::new();
+        self.write_header(&mut header)?;
+        self.cells.store.save_meta(&header)?;
+
+        for grid_index in self.get_droppable_grids() {
+            self.drop_grid(grid_index);
+        }
+        self.cells.store.flush()?;
+        Ok(saved)
+    }
+
+    fn header(&self) -> WorldHeader<&Player> {
+        WorldHeader {
+            grid_width: self.cells.grids.grid_width,
+            grid_height: self.cells.grids.grid_height,
+            gravity: self.gravity,
+            dt: self.dt,
+            next_cell_index: self.cells.next_cell_index,
+            seed: self.seed(),
+            generator: self.cells.generator_config.clone(),
+            rng: self.cells.rng.clone(),
+            stats: self.cells.stats,
+            player: &self.player,
+            bombs: self.bombs.clone(),
+        }
+    }
+
+    fn apply_header(&mut self, header: WorldHeader<Player>) {
+        self.gravity = header.gravity;
+        self.dt = header.dt;
+        self.cells.next_cell_index = header.next_cell_index;
+        if (header.seed, &header.generator) != (self.seed(), &self.cells.generator_config) {
+            self.cells.set_generator(header.generator, header.seed);
+        }
+        self.cells.rng = header.rng;
+        self.cells.stats = header.stats;
+        self.player = header.player;
+        self.bombs = header.bombs;
+    }
+
+    /// Serialize only the world header (player, bombs, gravity, seed, ...), without
+    /// any grids
+    pub fn write_header(&self, mut writer: impl Write) -> bincode::Result<()> {
+        write_version(&mut writer)?;
+        bincode::serialize_into(writer, &self.header())
+    }
+
+    /// Restore a world header written by `write_header`. Fails if it has a
+    /// different version, or if the stored grid dimensions differ from this
+    /// universe's.
+    pub fn read_header(&mut self, mut reader: impl Read) -> Result<(), Box<dyn std::error::Error>> {
+        read_version(&mut reader)?;
+        let header: WorldHeader<Player> = bincode::deserialize_from(reader)?;
+        if (header.grid_width, header.grid_height)
+            != (self.cells.grids.grid_width, self.cells.grids.grid_height)
+        {
+            return Err(format!(
+                "grid size mismatch: stored {}x{}, expected {}x{}",
+                header.grid_width,
+                header.grid_height,
+                self.cells.grids.grid_width,
+                self.cells.grids.grid_height
+            )
+            .into());
+        }
+        self.apply_header(header);
         Ok(())
     }
 
+    /// Serialize the whole universe: world header plus every loaded grid
+    pub fn write_to(&self, mut writer: impl Write) -> bincode::Result<()> {
+        let mut grids = Vec::new();
+        for grid_index in self.cells.get_loaded_grids() {
+            let grid = self.cells.grids.get(grid_index).unwrap();
+            grids.push((grid_index, grid.to_vec()?));
+        }
+        let data = UniverseSerialData {
+            header: self.header(),
+            grids,
+        };
+        write_version(&mut writer)?;
+        bincode::serialize_into(writer, &data)
+    }
+
+    /// Deserialize a universe written by `write_to`
+    pub fn read_from(mut reader: impl Read) -> bincode::Result<Universe> {
+        read_version(&mut reader)?;
+        let data: UniverseSerialData<Player> = bincode::deserialize_from(reader)?;
+        let (width, height) = (data.header.grid_width, data.header.grid_height);
+        let mut universe = Universe::new(width, height);
+        universe.apply_header(data.header);
+        for (grid_index, bytes) in data.grids {
+            let grid = UniverseGrid::from_slice(&bytes, grid_index, width, height)?;
+            universe.cells.load_from_storage(grid_index, grid);
+        }
+        Ok(universe)
+    }
+
     pub fn tick(&mut self) {
         self.cells.stats.ticks += 1;
 
@@ -767,8 +1678,11 @@ impl Universe {
 
             self.player.update_pos(&self.cells, self.dt);
             self.cells.update_pos(self.dt);
+            self.update_bombs();
             self.zero_forces();
         }
+        self.cells.flow_liquids();
+        self.cells.continue_support_searches();
 
         // player gets a bit sick as time passes
         self.player.life = self.player.life.saturating_sub(10000);
@@ -780,13 +1694,852 @@ impl Universe {
         self.cells.stats.get_and_reset()
     }
 
+    pub fn seed(&self) -> u32 {
+        self.cells.seed
+    }
+
+    pub fn generator_config(&self) -> &GeneratorConfig {
+        &self.cells.generator_config
+    }
+
+    /// Switches to another world generator. Only affects grids generated
+    /// from now on, so should be called before any grid is loaded.
+    pub fn set_generator(&mut self, config: GeneratorConfig) {
+        self.cells.set_generator(config, self.cells.seed);
+    }
+
     pub fn new(width: usize, height: usize) -> Universe {
+        Self::new_with_seed(width, height, 0)
+    }
+
+    /// A universe whose terrain (and everything else random) derives from
+    /// `seed`
+    pub fn new_with_seed(width: usize, height: usize, seed: u32) -> Universe {
         Universe {
-            cells: UniverseCells::new(width, height),
+            cells: UniverseCells::new(width, height, seed),
             gravity: V2 { x: 0.0, y: 0.1 },
             dt: 0.01,
 
             player: Player::new(1, 1),
+            bombs: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moving_cell(pos: V2i) -> Cell {
+        Cell {
+            index: CellIndex::default(),
+            color: Material::Sand.color(1.0),
+            inertia: Inertia {
+                velocity: V2::new(0.5, 0.0),
+                force: V2::zero(),
+                pos: pos.to_v2(),
+                mass: Material::Sand.density(),
+                collision_stats: 0,
+            },
+            material: Material::Sand,
+            state: CellState::Moving,
         }
     }
+
+    #[test]
+    fn test_universe_serialization() {
+        let mut universe = Universe::new(16, 16);
+        universe.set_generator(GeneratorConfig::Sky);
+        let grid_index = GridIndex::from_pos(V2i::new(3, 3), 16, 16);
+        universe.cells.ensure_grid(grid_index);
+        universe.cells.remove_cell(V2i::new(3, 3));
+        universe.cells.add_cell(moving_cell(V2i::new(3, 3)));
+        universe.player.inertia.pos = V2::new(5.0, 6.0);
+        universe.player.life = 1234;
+
+        let mut bytes = Vec::new();
+        universe.write_to(&mut bytes).unwrap();
+        let restored = Universe::read_from(bytes.as_slice()).unwrap();
+
+        assert_eq!(restored.get_loaded_grids(), vec![grid_index]);
+        assert_eq!(restored.player.inertia.pos, V2::new(5.0, 6.0));
+        assert_eq!(restored.player.life, 1234);
+        // loaded cells get fresh indices, after the ones already issued
+        assert!(restored.cells.next_cell_index >= universe.cells.next_cell_index);
+        assert_eq!(restored.cells.moving_cells.len(), 1);
+
+        let grid = restored.cells.grids.get(grid_index).unwrap();
+        let value = grid.get(V2i::new(3, 3)).value;
+        assert_eq!(value.len(), 1);
+        assert_eq!(value[0].borrow().material, Material::Sand);
+        assert_eq!(value[0].borrow().inertia.velocity, V2::new(0.5, 0.0));
+    }
+
+    #[test]
+    fn test_grid_loaded_from_store() {
+        let mut universe = Universe::new(16, 16);
+        let grid_index = GridIndex::from_pos(V2i::new(-20, -40), 16, 16);
+        universe.cells.ensure_grid(grid_index);
+        universe.cells.add_cell(moving_cell(V2i::new(-20, -40)));
+
+        // dirty grids are not dropped before they are stored
+        universe.drop_grid(grid_index);
+        assert_eq!(universe.get_loaded_grids(), vec![grid_index]);
+
+        universe.cells.store_grid(grid_index).unwrap();
+        universe.drop_grid(grid_index);
+        assert!(universe.get_loaded_grids().is_empty());
+
+        // ensure_grid prefers the stored grid over generating a new one
+        universe.cells.ensure_grid(grid_index);
+        let grid = universe.cells.grids.get(grid_index).unwrap();
+        assert!(!grid.is_dirty());
+        assert_eq!(grid.get(V2i::new(-20, -40)).value.len(), 1);
+    }
+
+    #[test]
+    fn test_undecodable_grid_not_generated_over() {
+        let mut universe = Universe::new(16, 16);
+        let grid_index = GridIndex::from_pos(V2i::new(-20, -40), 16, 16);
+        universe.cells.ensure_grid(grid_index);
+        universe.cells.store_grid(grid_index).unwrap();
+        let mut bytes = universe.store().take_grid(grid_index).unwrap().unwrap();
+
+        // stored by another version
+        bytes[0] += 1;
+        let mut resumed = Universe::new(16, 16);
+        resumed.store().save_grid(grid_index, &bytes).unwrap();
+        assert!(!resumed.cells.ensure_grid(grid_index));
+        assert!(resumed.get_loaded_grids().is_empty());
+        assert!(resumed.store().take_grid(grid_index).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_grid_waits_for_staged_copy() {
+        let mut universe = Universe::new(16, 16);
+        let grid_index = GridIndex::from_pos(V2i::new(-20, -40), 16, 16);
+        universe.cells.ensure_grid(grid_index);
+        universe.cells.add_cell(moving_cell(V2i::new(-20, -40)));
+        universe.cells.store_grid(grid_index).unwrap();
+        let bytes = universe.store().take_grid(grid_index).unwrap().unwrap();
+
+        let mut resumed = Universe::new(16, 16);
+        resumed.set_store(Box::new(MemStore::staging())).unwrap();
+        // not generated while its stored copy may still be on its way
+        let range = resumed
+            .cells
+            .get_range(V2i::new(-20, -40), V2i::new(-19, -39));
+        assert!(range[0].1.is_empty());
+        assert!(resumed.get_loaded_grids().is_empty());
+
+        resumed.store().save_grid(grid_index, &bytes).unwrap();
+        assert!(resumed.cells.ensure_grid(grid_index));
+        assert_eq!(
+            resumed
+                .cells
+                .get_range(V2i::new(-20, -40), V2i::new(-19, -39))[0]
+                .1
+                .len(),
+            1
+        );
+
+        // generated once the frontend tells it has none
+        let other = GridIndex::from_pos(V2i::new(-40, -40), 16, 16);
+        assert!(!resumed.cells.ensure_grid(other));
+        resumed.store().mark_absent(other);
+        assert!(resumed.cells.ensure_grid(other));
+    }
+
+    #[test]
+    fn test_stream_stores_only_dirty_grids() {
+        let mut universe = Universe::new(16, 16);
+        // generated grids are pristine, nothing to store
+        assert!(universe.stream().unwrap().is_empty());
+        let loaded = universe.get_loaded_grids();
+        assert!(!loaded.is_empty());
+        for grid_index in loaded.iter() {
+            assert_eq!(universe.store().load_grid(*grid_index).unwrap(), None);
+        }
+
+        let pos = V2i::new(-20, -40);
+        universe
+            .cells
+            .ensure_grid(universe.cells.grids.pos_to_index(pos));
+        universe.cells.add_cell(moving_cell(pos));
+        let grid_index = universe.cells.grids.pos_to_index(pos);
+        assert_eq!(universe.get_dirty_grids(), vec![grid_index]);
+
+        assert_eq!(universe.stream().unwrap(), vec![grid_index]);
+        assert!(universe.get_dirty_grids().is_empty());
+        assert!(universe.store().load_grid(grid_index).unwrap().is_some());
+        // far away from the player, so it was dropped after being stored
+        assert!(!universe.get_loaded_grids().contains(&grid_index));
+    }
+
+    // Collisions between two cells moving toward each other at `left` and
+    // the position to its right
+    fn count_collisions(left: V2i) -> usize {
+        let mut universe = Universe::new(16, 16);
+        let right = left.plus(V2i::new(1, 0));
+        universe
+            .cells
+            .ensure_grids(left, right.plus(V2i::new(1, 1)));
+        universe.cells.add_cell(moving_cell(left));
+        universe.cells.add_cell(Cell {
+            inertia: Inertia {
+                velocity: V2::new(-0.5, 0.0),
+                ..moving_cell(right).inertia
+            },
+            ..moving_cell(right)
+        });
+
+        universe.cells.collect_collisions();
+        universe.cells.collisions_list.len()
+    }
+
+    #[test]
+    fn test_collision_across_grid_edge() {
+        let inside = count_collisions(V2i::new(5, -40));
+        assert!(inside > 0);
+        // x = 15 and x = 16 are in different grids
+        assert_eq!(count_collisions(V2i::new(15, -40)), inside);
+    }
+
+    #[test]
+    fn test_cell_thrown_into_unloaded_grid() {
+        let mut universe = Universe::new(16, 16);
+        let grid_index = GridIndex::from_pos(V2i::new(8, -40), 16, 16);
+        universe.cells.ensure_grid(grid_index);
+        universe.cells.add_cell(Cell {
+            inertia: Inertia {
+                velocity: V2::new(1.0, -1.0),
+                ..moving_cell(V2i::new(14, -40)).inertia
+            },
+            ..moving_cell(V2i::new(14, -40))
+        });
+        let cell_ref = universe.cells.moving_cells.values().next().unwrap().clone();
+
+        for _ in 0..10 {
+            universe.tick();
+        }
+        // the cell waits at the border, still moving and still in its grid
+        let pos = cell_ref.borrow().inertia.pos.round();
+        assert_eq!(universe.cells.grids.pos_to_index(pos), grid_index);
+        assert_eq!(universe.cells.moving_cells.len(), 1);
+        let grid = universe.cells.grids.get(grid_index).unwrap();
+        assert!(Rc::ptr_eq(&grid.get(pos).value[0], &cell_ref));
+
+        // once the grids around are loaded it keeps going
+        universe
+            .cells
+            .ensure_grids(V2i::new(-16, -64), V2i::new(32, 0));
+        for _ in 0..10 {
+            universe.tick();
+        }
+        let pos = cell_ref.borrow().inertia.pos.round();
+        assert_ne!(universe.cells.grids.pos_to_index(pos), grid_index);
+        let grid_index = universe.cells.grids.pos_to_index(pos);
+        let grid = universe.cells.grids.get(grid_index).unwrap();
+        assert!(Rc::ptr_eq(&grid.get(pos).value[0], &cell_ref));
+    }
+
+    #[test]
+    fn test_moving_cells_restored_from_store() {
+        let mut universe = Universe::new(16, 16);
+        let pos = V2i::new(-20, -40);
+        let grid_index = GridIndex::from_pos(pos, 16, 16);
+        universe.cells.ensure_grid(grid_index);
+        universe.cells.add_cell(moving_cell(pos));
+        universe.cells.store_grid(grid_index).unwrap();
+        universe.drop_grid(grid_index);
+        assert!(universe.cells.moving_cells.is_empty());
+
+        // meanwhile other cells were added
+        let other_pos = V2i::new(20, -40);
+        universe
+            .cells
+            .ensure_grid(GridIndex::from_pos(other_pos, 16, 16));
+        universe.cells.add_cell(moving_cell(other_pos));
+        let other_index = universe.cells.next_cell_index;
+
+        universe.cells.ensure_grid(grid_index);
+        assert_eq!(universe.cells.moving_cells.len(), 2);
+        let grid = universe.cells.grids.get(grid_index).unwrap();
+        let cell = grid.get(pos).value[0].borrow();
+        assert!(cell.index.index > other_index);
+        assert!(universe.cells.moving_cells.contains_key(&cell.index));
+    }
+
+    #[test]
+    fn test_generated_cells_have_unique_indices() {
+        let mut universe = Universe::new(16, 16);
+        let start = V2i::new(0, 0);
+        let end = V2i::new(32, 32);
+        let mut indices = FnvHashSet::default();
+        for (_, cells) in universe.cells.get_range(start, end) {
+            for cell in cells {
+                assert!(indices.insert(cell.borrow().index));
+            }
+        }
+        assert!(!indices.is_empty());
+    }
+
+    #[test]
+    fn test_world_header_round_trip() {
+        let mut universe = Universe::new(16, 16);
+        universe.gravity = V2::new(0.0, 0.5);
+        universe.cells.set_generator(GeneratorConfig::Flat, 42);
+        universe.cells.next_cell_index = 1000;
+        universe.cells.stats.ticks = 7;
+        universe.player.inertia.pos = V2::new(-30.0, 12.0);
+        universe.player.life = 99;
+        universe.player.direction = -1;
+        universe.throw_bomb(V2::new(3.0, -40.0), V2::new(1.0, 0.0));
+        universe.cells.rng.random::<u64>();
+
+        let mut bytes = Vec::new();
+        universe.write_header(&mut bytes).unwrap();
+
+        let mut restored = Universe::new(16, 16);
+        restored.read_header(bytes.as_slice()).unwrap();
+        assert_eq!(restored.gravity, V2::new(0.0, 0.5));
+        assert_eq!(restored.seed(), 42);
+        assert_eq!(restored.cells.generator_config, GeneratorConfig::Flat);
+        assert_eq!(restored.cells.next_cell_index, 1000);
+        assert_eq!(restored.cells.stats.ticks, 7);
+        assert_eq!(restored.player.inertia.pos, V2::new(-30.0, 12.0));
+        assert_eq!(restored.player.life, 99);
+        assert_eq!(restored.player.direction, -1);
+        assert_eq!(restored.bombs(), universe.bombs());
+        // draws on where the saved world left off
+        assert_eq!(
+            restored.cells.rng.random::<u64>(),
+            universe.cells.rng.random::<u64>()
+        );
+
+        // a different grid size or version is rejected
+        assert!(Universe::new(32, 32).read_header(bytes.as_slice()).is_err());
+        bytes[0] += 1;
+        assert!(restored.read_header(bytes.as_slice()).is_err());
+    }
+
+    fn png(width: u32, height: u32, pixels: &[[u8; 4]]) -> Vec<u8> {
+        let img = image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba(pixels[(y * width + x) as usize])
+        });
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    // The cells at `pos`, in a loaded grid
+    fn cells_at(universe: &Universe, pos: V2i) -> &[GridCellRef<Cell>] {
+        let grids = &universe.cells.grids;
+        grids.get(grids.pos_to_index(pos)).unwrap().get(pos).value
+    }
+
+    // A universe with 16 by 16 grids made by `generator`, with `rows` drawn
+    // into it from `origin` on: `#` is stone, `~` water at rest, `w` moving
+    // water and anything else leaves the world as it is
+    fn universe_with(generator: GeneratorConfig, origin: V2i, rows: &[&str]) -> Universe {
+        let mut universe = Universe::new_with_seed(16, 16, 0);
+        universe.set_generator(generator);
+        let (stone, water, flowing) = (
+            Color::rgb(127, 63, 0),
+            Color::rgb(0, 0, 255),
+            Color::rgb(0, 255, 255),
+        );
+        let pixels: Vec<[u8; 4]> = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|c| match c {
+                '#' => [stone.r, stone.g, stone.b, 255],
+                '~' => [water.r, water.g, water.b, 255],
+                'w' => [flowing.r, flowing.g, flowing.b, 255],
+                _ => [0, 0, 0, 0],
+            })
+            .collect();
+        let rule = |color, material, moving| ColorRule {
+            color,
+            material,
+            moving,
+        };
+        let mapping = ColorMapping::new(vec![
+            rule(stone, Material::Stone, false),
+            rule(water, Material::Water, false),
+            rule(flowing, Material::Water, true),
+        ]);
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        universe
+            .cells
+            .import_image(&png(width, height, &pixels), origin, &mapping)
+            .unwrap();
+        universe
+    }
+
+    #[test]
+    fn test_import_image() {
+        let mut universe = Universe::new_with_seed(16, 16, 0);
+        let stone = Material::Stone.color(1.0);
+        let sand = Material::Sand.color(1.0);
+        let bytes = png(
+            3,
+            1,
+            &[
+                [stone.r, stone.g, stone.b, 255],
+                [0, 0, 0, 0],
+                [sand.r, sand.g, sand.b, 255],
+            ],
+        );
+        // straddling the edge of grids in the sky
+        let origin = V2i::new(-2, -40);
+        let count = universe
+            .cells
+            .import_image(&bytes, origin, &ColorMapping::default())
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let cells = |x| cells_at(&universe, origin.plus(V2i::new(x, 0))).to_vec();
+        let wall = cells(0);
+        assert_eq!(wall.len(), 1);
+        assert_eq!(wall[0].borrow().material, Material::Stone);
+        assert_eq!(wall[0].borrow().state, CellState::Fixed);
+        assert!(cells(1).is_empty());
+        let grain = cells(2);
+        assert_eq!(grain[0].borrow().material, Material::Sand);
+        assert!(universe
+            .cells
+            .moving_cells
+            .contains_key(&grain[0].borrow().index));
+
+        let mut dirty = universe.get_dirty_grids();
+        dirty.sort_by_key(|idx| idx.grid_offset.x);
+        assert_eq!(
+            dirty,
+            vec![
+                GridIndex::from_pos(origin, 16, 16),
+                GridIndex::from_pos(origin.plus(V2i::new(2, 0)), 16, 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_liquid_wakes_when_dug_out() {
+        let origin = V2i::new(3, 3);
+        let mut universe = universe_with(GeneratorConfig::Sky, origin, &["~", "#"]);
+        assert!(universe.cells.moving_cells.is_empty());
+
+        // taking away the stone under the water lets it flow
+        universe.cells.remove_cell(origin.plus(V2i::new(0, 1)));
+        assert_eq!(universe.cells.moving_cells.len(), 1);
+        let water = universe
+            .cells
+            .moving_cells
+            .values()
+            .next()
+            .unwrap()
+            .borrow();
+        assert_eq!(water.material, Material::Water);
+        assert_eq!(water.state, CellState::Moving);
+        assert_eq!(water.inertia.mass, Material::Water.density());
+    }
+
+    #[test]
+    fn test_removed_liquid_forgets_idle_ticks() {
+        // a drop of water in a stone cup, with nowhere to flow
+        let origin = V2i::new(3, 3);
+        let mut universe = universe_with(GeneratorConfig::Sky, origin, &["#w#", "###"]);
+        universe.cells.flow_liquids();
+        assert_eq!(universe.cells.liquid_idle_ticks.len(), 1);
+
+        universe.cells.remove_cell(origin.plus(V2i::new(1, 0)));
+        assert!(universe.cells.liquid_idle_ticks.is_empty());
+    }
+
+    #[test]
+    fn test_liquid_levels_out_in_container() {
+        // a stone container 8 cells wide inside, with two columns of water
+        // 8 cells high at its left
+        let origin = V2i::new(20, -30);
+        let mut universe = universe_with(
+            GeneratorConfig::Sky,
+            origin,
+            &[
+                "#........#",
+                "#ww......#",
+                "#ww......#",
+                "#ww......#",
+                "#ww......#",
+                "#ww......#",
+                "#ww......#",
+                "#ww......#",
+                "#ww......#",
+                "##########",
+            ],
+        );
+
+        for _ in 0..200 {
+            universe.tick();
+        }
+
+        // all at rest, two cells deep everywhere
+        assert!(universe.cells.moving_cells.is_empty());
+        let depths: Vec<usize> = (1..9)
+            .map(|x| {
+                (0..9)
+                    .filter(|y| {
+                        cells_at(&universe, origin.plus(V2i::new(x, *y)))
+                            .iter()
+                            .any(|cell| cell.borrow().material.is_liquid())
+                    })
+                    .count()
+            })
+            .collect();
+        assert_eq!(depths, vec![2; 8]);
+    }
+
+    #[test]
+    fn test_sand_sinks_in_settled_pool() {
+        // a stone container 4 cells wide inside, full of water at rest 3
+        // cells deep
+        let origin = V2i::new(20, -30);
+        let mut universe = universe_with(
+            GeneratorConfig::Sky,
+            origin,
+            &["#....#", "#....#", "#~~~~#", "#~~~~#", "#~~~~#", "######"],
+        );
+        assert!(universe.cells.moving_cells.is_empty());
+
+        // a grain dropped onto it ends up at the bottom, under the water
+        universe
+            .cells
+            .add_cell(moving_cell(origin.plus(V2i::new(2, 0))));
+        for _ in 0..100 {
+            universe.tick();
+        }
+        let materials_at = |x: i32, y: i32| -> Vec<Material> {
+            cells_at(&universe, origin.plus(V2i::new(x, y)))
+                .iter()
+                .map(|cell| cell.borrow().material)
+                .collect()
+        };
+        let sand: Vec<(i32, i32)> = (1..5)
+            .flat_map(|x| (0..5).map(move |y| (x, y)))
+            .filter(|(x, y)| materials_at(*x, *y).contains(&Material::Sand))
+            .collect();
+        assert_eq!(sand.len(), 1);
+        assert_eq!(sand[0].1, 4);
+        let water: usize = (1..5)
+            .flat_map(|x| (0..5).map(move |y| (x, y)))
+            .map(|(x, y)| materials_at(x, y).iter().filter(|m| m.is_liquid()).count())
+            .sum();
+        assert_eq!(water, 12);
+    }
+
+    #[test]
+    fn test_sand_pile_angle_of_repose() {
+        let mut universe = Universe::new_with_seed(32, 32, 0);
+        universe.set_generator(GeneratorConfig::Flat);
+        universe
+            .cells
+            .ensure_grids(V2i::new(-64, -64), V2i::new(96, 64));
+        // poured grain by grain onto the ground at y = 32, a little off to
+        // the sides so they don't stack up in a column
+        for i in 0..250 {
+            let jitter = |n: usize| (n % 5) as f64 * 0.1 - 0.2;
+            universe.cells.add_cell(Cell {
+                inertia: Inertia {
+                    velocity: V2::new(jitter(i * 7), 0.0),
+                    pos: V2::new(16.0 + jitter(i * 3), 0.0),
+                    ..moving_cell(V2i::new(16, 0)).inertia
+                },
+                ..moving_cell(V2i::new(16, 0))
+            });
+            universe.tick();
+            universe.tick();
+        }
+        while !universe.cells.moving_cells.is_empty() {
+            universe.tick();
+        }
+
+        // the height of each column of the pile
+        let heights: Vec<(i32, i32)> = (-32..64)
+            .filter_map(|x| {
+                (0..32)
+                    .find(|y| !cells_at(&universe, V2i::new(x, *y)).is_empty())
+                    .map(|y| (x, 32 - y))
+            })
+            .collect();
+        let width_at = |height: i32| {
+            let columns: Vec<i32> = heights
+                .iter()
+                .filter(|(_, h)| *h >= height)
+                .map(|(x, _)| *x)
+                .collect();
+            columns.last().unwrap() - columns[0] + 1
+        };
+        // the slope of its flanks, between a quarter and three quarters of
+        // its height
+        let peak = heights.iter().map(|(_, h)| *h).max().unwrap();
+        let (low, high) = (peak / 4, 3 * peak / 4);
+        let slope = 2.0 * (high - low) as f64 / (width_at(low) - width_at(high)) as f64;
+        let repose = Material::Sand.static_friction().atan();
+        assert!(
+            (slope.atan() - repose).abs() < 5f64.to_radians(),
+            "slope {} degrees",
+            slope.atan().to_degrees()
+        );
+    }
+
+    #[test]
+    fn test_sleeping_cell_wakes_when_disturbed() {
+        let mut universe = Universe::new_with_seed(16, 16, 0);
+        universe.set_generator(GeneratorConfig::Flat);
+        universe
+            .cells
+            .ensure_grids(V2i::new(-16, -16), V2i::new(32, 32));
+        // sand grains dropped on the ground at y = 16 go to sleep
+        let grain = |universe: &Universe, pos: V2i| cells_at(universe, pos)[0].clone();
+        for x in [2, 8] {
+            universe.cells.add_cell(Cell {
+                inertia: Inertia {
+                    velocity: V2::zero(),
+                    ..moving_cell(V2i::new(x, 15)).inertia
+                },
+                ..moving_cell(V2i::new(x, 15))
+            });
+        }
+        universe.tick();
+        assert!(universe.cells.moving_cells.is_empty());
+        assert_eq!(
+            grain(&universe, V2i::new(2, 15)).borrow().state,
+            CellState::Sleeping
+        );
+        // at rest it keeps its mass, the state alone makes it immovable
+        assert_eq!(
+            grain(&universe, V2i::new(2, 15)).borrow().inertia.mass,
+            Material::Sand.density()
+        );
+
+        // removing a cell next to it wakes it, the ground stays fixed
+        universe.cells.remove_cell(V2i::new(1, 16));
+        assert_eq!(universe.cells.moving_cells.len(), 1);
+        assert!(grain(&universe, V2i::new(2, 15)).borrow().is_moving());
+
+        // a soft hit doesn't wake the other one, a hard one does
+        let stone = |x: i32, velocity: f64| Cell {
+            inertia: Inertia {
+                velocity: V2::new(velocity, 0.0),
+                mass: Material::Stone.density(),
+                ..moving_cell(V2i::new(x, 15)).inertia
+            },
+            material: Material::Stone,
+            ..moving_cell(V2i::new(x, 15))
+        };
+        universe.cells.add_cell(stone(7, 0.5));
+        universe.cells.calc_collisions(universe.dt);
+        assert!(!grain(&universe, V2i::new(8, 15)).borrow().is_moving());
+        universe.cells.add_cell(stone(9, -1.5));
+        universe.cells.calc_collisions(universe.dt);
+        assert!(grain(&universe, V2i::new(8, 15)).borrow().is_moving());
+    }
+
+    #[test]
+    fn test_ledge_caves_in_when_dug_under() {
+        // a pillar standing on the ground at y = 16, with a ledge at its top
+        let origin = V2i::new(5, 12);
+        let mut universe = universe_with(
+            GeneratorConfig::Flat,
+            origin,
+            &["#####", "#....", "#....", "#...."],
+        );
+        for x in -1..2 {
+            for y in -1..2 {
+                universe.cells.ensure_grid(GridIndex {
+                    grid_offset: V2i::new(x, y),
+                });
+            }
+        }
+        assert!(universe.cells.moving_cells.is_empty());
+
+        // digging into the ground holds, there's more of it below
+        universe.cells.remove_cell(V2i::new(0, 20));
+        assert!(universe.cells.moving_cells.is_empty());
+
+        // digging through the pillar leaves the top of it and the ledge
+        // without support, the stump stays
+        universe.cells.remove_cell(origin.plus(V2i::new(0, 2)));
+        assert_eq!(universe.cells.moving_cells.len(), 6);
+        for _ in 0..100 {
+            universe.tick();
+        }
+        let is_empty = |pos: V2i| cells_at(&universe, pos).is_empty();
+        assert!((5..10).all(|x| is_empty(V2i::new(x, 12))));
+        assert!(!is_empty(origin.plus(V2i::new(0, 3))));
+    }
+
+    #[test]
+    fn test_big_floating_structure_caves_in() {
+        // a slab of stone in the sky, too big to check in one go
+        let (width, height) = (40, 30);
+        let origin = V2i::new(0, -100);
+        let row = "#".repeat(width);
+        let mut universe = universe_with(GeneratorConfig::Sky, origin, &vec![row.as_str(); height]);
+        universe.cells.ensure_grids(
+            origin.minus(V2i::new(16, 16)),
+            origin.plus(V2i::new(56, 46)),
+        );
+
+        // digging at its bottom, it isn't held up by its size: it falls once
+        // the check is done
+        universe
+            .cells
+            .remove_cell(origin.plus(V2i::new(0, height as i32 - 1)));
+        assert!(universe.cells.moving_cells.is_empty());
+        universe.cells.continue_support_searches();
+        assert_eq!(universe.cells.moving_cells.len(), width * height - 1);
+        assert!(universe.cells.support_searches.is_empty());
+    }
+
+    #[test]
+    fn test_floating_block_dug_from_above_caves_in() {
+        let origin = V2i::new(4, -100);
+        let mut universe = universe_with(GeneratorConfig::Sky, origin, &["###", "###"]);
+        universe.cells.ensure_grids(
+            origin.minus(V2i::new(16, 16)),
+            origin.plus(V2i::new(16, 16)),
+        );
+
+        // its bottom row isn't ground, nothing reaches down from it
+        universe.cells.remove_cell(origin);
+        assert_eq!(universe.cells.moving_cells.len(), 5);
+    }
+
+    #[test]
+    fn test_support_search_skips_replaced_cells() {
+        let (width, height) = (40, 30);
+        let origin = V2i::new(0, -100);
+        let row = "#".repeat(width);
+        let mut universe = universe_with(GeneratorConfig::Sky, origin, &vec![row.as_str(); height]);
+        universe.cells.ensure_grids(
+            origin.minus(V2i::new(16, 16)),
+            origin.plus(V2i::new(56, 46)),
+        );
+        let bottom = origin.plus(V2i::new(0, height as i32 - 1));
+        universe.cells.remove_cell(bottom);
+        assert_eq!(universe.cells.support_searches.len(), 1);
+
+        // a cell the pending search reached is replaced by another one
+        let pos = bottom.plus(V2i::new(1, 0));
+        let cell = *cells_at(&universe, pos)[0].borrow();
+        universe.cells.clear_pos(pos);
+        let grid_index = universe.cells.grids.pos_to_index(pos);
+        let replacement = universe.cells.insert_cell(grid_index, pos, cell);
+
+        universe.cells.continue_support_searches();
+        assert_eq!(universe.cells.moving_cells.len(), width * height - 2);
+        assert!(!replacement.borrow().is_moving());
+    }
+
+    #[test]
+    fn test_hard_cells_resist_explosion() {
+        // water and stone as close to the center
+        let origin = V2i::new(4, -100);
+        let mut universe = universe_with(GeneratorConfig::Sky, origin, &["~...#"]);
+        let center = origin.plus(V2i::new(2, 0)).to_v2();
+
+        universe.cells.explode(center, 6.0, 1.0);
+        assert!(cells_at(&universe, origin).is_empty());
+        let stone = cells_at(&universe, origin.plus(V2i::new(4, 0)))[0].borrow();
+        assert!(stone.is_moving());
+        assert!(stone.inertia.velocity.x > 0.0);
+    }
+
+    #[test]
+    fn test_explosion() {
+        let mut universe = Universe::new_with_seed(16, 16, 0);
+        universe.set_generator(GeneratorConfig::Flat);
+        universe
+            .cells
+            .ensure_grids(V2i::new(-16, 0), V2i::new(32, 48));
+        let center = V2::new(8.0, 24.0);
+        // the player's center is 3 cells to the left of the explosion
+        let (w, h) = (universe.player.w as f64, universe.player.h as f64);
+        universe.player.inertia.pos = V2::new(5.0 - w / 2.0, 24.0 - h / 2.0);
+
+        universe.explode(center, 6.0, 1.0);
+        // destroyed at the center
+        assert!(cells_at(&universe, V2i::new(8, 24)).is_empty());
+        // thrown away from it around the center
+        let thrown = cells_at(&universe, V2i::new(12, 24))[0].borrow();
+        assert!(thrown.is_moving());
+        assert!(thrown.inertia.velocity.x > 0.0);
+        let thrown = cells_at(&universe, V2i::new(8, 19))[0].borrow();
+        assert!(thrown.inertia.velocity.y < 0.0);
+        // out of reach
+        assert_eq!(
+            cells_at(&universe, V2i::new(16, 24))[0].borrow().state,
+            CellState::Fixed
+        );
+        assert!(universe.player.inertia.velocity.x < 0.0);
+    }
+
+    #[test]
+    fn test_bomb_explodes_on_hit() {
+        let mut universe = Universe::new_with_seed(16, 16, 0);
+        universe.set_generator(GeneratorConfig::Flat);
+        universe
+            .cells
+            .ensure_grids(V2i::new(-16, -16), V2i::new(32, 32));
+        universe.throw_bomb(V2::new(8.0, 10.0), V2::new(0.0, 0.5));
+        for _ in 0..20 {
+            universe.tick();
+        }
+        assert!(universe.bombs().is_empty());
+        // a crater where it hit the ground (stone) at y = 16
+        assert!(cells_at(&universe, V2i::new(8, 16)).is_empty());
+    }
+
+    #[test]
+    fn test_bomb_waits_at_unloaded_grid() {
+        let mut universe = Universe::new_with_seed(16, 16, 0);
+        universe.set_generator(GeneratorConfig::Sky);
+        universe
+            .cells
+            .ensure_grids(V2i::new(0, 0), V2i::new(16, 16));
+        universe.throw_bomb(V2::new(8.0, 10.0), V2::new(0.0, 1.0));
+        for _ in 0..10 {
+            universe.tick();
+        }
+        // stopped at the bottom of the loaded grid, without loading the next
+        assert_eq!(universe.bombs().len(), 1);
+        assert!(universe.bombs()[0].inertia.pos.y < 15.5);
+        assert_eq!(universe.bombs()[0].inertia.velocity, V2::zero());
+        assert_eq!(universe.get_loaded_grids().len(), 1);
+        // exploding there doesn't load it either
+        universe.explode(V2::new(8.0, 15.0), BOMB_RADIUS, BOMB_STRENGTH);
+        assert_eq!(universe.get_loaded_grids().len(), 1);
+
+        // it falls on once it's loaded
+        universe
+            .cells
+            .ensure_grids(V2i::new(0, 16), V2i::new(16, 32));
+        universe.tick();
+        assert!(universe.bombs()[0].inertia.pos.y > 15.5);
+    }
+
+    #[test]
+    fn test_same_seed_same_world() {
+        let range = |seed| {
+            let mut universe = Universe::new_with_seed(16, 16, seed);
+            universe
+                .cells
+                .get_range(V2i::new(0, 0), V2i::new(64, 64))
+                .into_iter()
+                .map(|(pos, cells)| (pos, cells.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(range(7), range(7));
+        assert_ne!(range(7), range(8));
+    }
 }
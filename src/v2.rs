@@ -14,11 +14,11 @@ pub struct V2i {
 }
 
 impl V2i {
-    pub fn new(x: i32, y: i32) -> V2i {
+    pub const fn new(x: i32, y: i32) -> V2i {
         V2i { x, y }
     }
 
-    pub fn to_v2(&self) -> V2 {
+    pub fn to_v2(self) -> V2 {
         V2 {
             x: self.x as f64,
             y: self.y as f64,
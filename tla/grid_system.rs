@@ -4,7 +4,7 @@ use std::collections::{HashMap, HashSet};
 pub struct PlayerId(u32);
 
 impl PlayerId {
-    pub fn new(id: u32) -> Self {
+    pub const fn new(id: u32) -> Self {
         PlayerId(id)
     }
 }
@@ -16,6 +16,10 @@ impl GridPos {
     pub fn new(pos: u32) -> Self {
         GridPos(pos)
     }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
 }
 
 // Represents the status of a grid in storage
@@ -316,8 +320,8 @@ mod tests {
 
     #[test]
     fn test_player_moves() {
-        let mut grid_positions = HashSet::from([GridPos::new(1), GridPos::new(2), GridPos::new(3)]);
-        let mut player_ids = HashSet::from([PlayerId::new(101)]);
+        let grid_positions = HashSet::from([GridPos::new(1), GridPos::new(2), GridPos::new(3)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions, player_ids);
 
         // Player can't move to an unloaded grid
@@ -340,226 +344,264 @@ mod tests {
 
     #[test]
     fn test_load_missing_grid() {
-        let mut grid_positions = HashSet::from([1]);
-        let player_ids = HashSet::from([101]);
+        let grid_positions = HashSet::from([GridPos::new(1)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions.clone(), player_ids.clone());
 
         // Grid is initially not_stored and not_loaded
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::NotStored)
         );
         assert_eq!(
-            system.get_grid_load_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
             Some(GridLoadStatus::NotLoaded)
         );
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unknown)
         );
 
-        assert!(system.load_missing_grid(1));
+        assert!(system.load_missing_grid(GridPos::new(1)));
 
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::NotStored)
         ); // Unchanged
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        );
+        assert_eq!(
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Pristine)
         );
     }
 
     #[test]
     fn test_load_stored_grid() {
-        let mut grid_positions = HashSet::from([1]);
-        let player_ids = HashSet::from([101]);
+        let grid_positions = HashSet::from([GridPos::new(1)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions.clone(), player_ids.clone());
 
         // Manually set a grid to stored and not loaded for testing this action
-        system.grid_store_status.insert(1, GridStoreStatus::Stored);
-        system.grid_load_status.insert(1, GridLoadStatus::NotLoaded);
-        system.grid_dirty_status.insert(1, GridDirtyStatus::Unknown);
+        system
+            .grid_store_status
+            .insert(GridPos::new(1), GridStoreStatus::Stored);
+        system
+            .grid_load_status
+            .insert(GridPos::new(1), GridLoadStatus::NotLoaded);
+        system
+            .grid_dirty_status
+            .insert(GridPos::new(1), GridDirtyStatus::Unknown);
 
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::Stored)
         );
         assert_eq!(
-            system.get_grid_load_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
             Some(GridLoadStatus::NotLoaded)
         );
 
-        assert!(system.load_stored_grid(1));
+        assert!(system.load_stored_grid(GridPos::new(1)));
 
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::Stored)
         ); // Unchanged
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        );
+        assert_eq!(
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         );
     }
 
     #[test]
     fn test_mark_dirty() {
-        let mut grid_positions = HashSet::from([1]);
-        let player_ids = HashSet::from([101]);
+        let grid_positions = HashSet::from([GridPos::new(1)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions.clone(), player_ids.clone());
 
         // Load a pristine grid
-        assert!(system.load_missing_grid(1));
+        assert!(system.load_missing_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Pristine)
         );
 
-        assert!(system.mark_dirty(1));
+        assert!(system.mark_dirty(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Dirty)
         );
 
         // Load an unmodified grid (simulate by loading a stored grid)
-        system.grid_load_status.insert(1, GridLoadStatus::NotLoaded);
-        system.grid_store_status.insert(1, GridStoreStatus::Stored);
-        assert!(system.load_stored_grid(1));
+        system
+            .grid_load_status
+            .insert(GridPos::new(1), GridLoadStatus::NotLoaded);
+        system
+            .grid_store_status
+            .insert(GridPos::new(1), GridStoreStatus::Stored);
+        assert!(system.load_stored_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         );
 
-        assert!(system.mark_dirty(1));
+        assert!(system.mark_dirty(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Dirty)
         );
     }
 
     #[test]
     fn test_store_grid() {
-        let mut grid_positions = HashSet::from([1]);
-        let player_ids = HashSet::from([101]);
+        let grid_positions = HashSet::from([GridPos::new(1)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions.clone(), player_ids.clone());
 
         // Load a dirty grid
-        assert!(system.load_missing_grid(1));
-        assert!(system.mark_dirty(1));
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded));
+        assert!(system.load_missing_grid(GridPos::new(1)));
+        assert!(system.mark_dirty(GridPos::new(1)));
+        assert_eq!(
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        );
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Dirty)
         );
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::NotStored)
         );
 
-        assert!(system.store_grid(1));
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded)); // Unchanged
+        assert!(system.store_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        ); // Unchanged
+        assert_eq!(
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         );
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::Stored)
         );
 
         // Test with unmodified grid
         system
             .grid_store_status
-            .insert(1, GridStoreStatus::NotStored); // Reset for next test
+            .insert(GridPos::new(1), GridStoreStatus::NotStored); // Reset for next test
         system
             .grid_dirty_status
-            .insert(1, GridDirtyStatus::Unmodified);
+            .insert(GridPos::new(1), GridDirtyStatus::Unmodified);
 
-        assert!(system.store_grid(1));
+        assert!(system.store_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::Stored)
         );
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         ); // Still unmodified
     }
 
     #[test]
     fn test_unload_pristine_unmodified_grid() {
-        let mut grid_positions = HashSet::from([1]);
-        let player_ids = HashSet::from([101]);
+        let grid_positions = HashSet::from([GridPos::new(1)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions.clone(), player_ids.clone());
 
         // Load a pristine grid
-        assert!(system.load_missing_grid(1));
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded));
+        assert!(system.load_missing_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        );
+        assert_eq!(
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Pristine)
         );
 
-        assert!(system.unload_pristine_unmodified_grid(1));
+        assert!(system.unload_pristine_unmodified_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_load_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
             Some(GridLoadStatus::NotLoaded)
         );
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Pristine)
         ); // Unchanged
 
         // Load an unmodified grid
-        system.grid_load_status.insert(1, GridLoadStatus::NotLoaded);
-        system.grid_store_status.insert(1, GridStoreStatus::Stored);
-        assert!(system.load_stored_grid(1));
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded));
+        system
+            .grid_load_status
+            .insert(GridPos::new(1), GridLoadStatus::NotLoaded);
+        system
+            .grid_store_status
+            .insert(GridPos::new(1), GridStoreStatus::Stored);
+        assert!(system.load_stored_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        );
+        assert_eq!(
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         );
 
-        assert!(system.unload_pristine_unmodified_grid(1));
+        assert!(system.unload_pristine_unmodified_grid(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_load_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
             Some(GridLoadStatus::NotLoaded)
         );
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         ); // Unchanged
     }
 
     #[test]
     fn test_store_and_unload_dirty_grid() {
-        let mut grid_positions = HashSet::from([1]);
-        let player_ids = HashSet::from([101]);
+        let grid_positions = HashSet::from([GridPos::new(1)]);
+        let player_ids = HashSet::from([PlayerId::new(101)]);
         let mut system = GridSystem::new(grid_positions.clone(), player_ids.clone());
 
         // Load a dirty grid
-        assert!(system.load_missing_grid(1));
-        assert!(system.mark_dirty(1));
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded));
+        assert!(system.load_missing_grid(GridPos::new(1)));
+        assert!(system.mark_dirty(GridPos::new(1)));
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        );
+        assert_eq!(
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Dirty)
         );
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::NotStored)
         );
 
-        assert!(system.store_and_unload_dirty_grid(1));
-        assert_eq!(system.get_grid_load_status(1), Some(GridLoadStatus::Loaded)); // IMPORTANT: Still loaded as per TLA+
+        assert!(system.store_and_unload_dirty_grid(GridPos::new(1)));
+        assert_eq!(
+            system.get_grid_load_status(GridPos::new(1)),
+            Some(GridLoadStatus::Loaded)
+        ); // IMPORTANT: Still loaded as per TLA+
         assert_eq!(
-            system.get_grid_dirty_status(1),
+            system.get_grid_dirty_status(GridPos::new(1)),
             Some(GridDirtyStatus::Unmodified)
         );
         assert_eq!(
-            system.get_grid_store_status(1),
+            system.get_grid_store_status(GridPos::new(1)),
             Some(GridStoreStatus::Stored)
         );
     }
@@ -7,7 +7,19 @@ const SIZE = 64;
 const CELL_SIZE = Math.min(canvas.clientWidth / SIZE, canvas.clientHeight / SIZE) | 0; // px
 
 
-const game = Game.new(SIZE, SIZE);
+// e.g. index.html?seed=1234&generator=flat for a different world
+const params = new URLSearchParams(window.location.search);
+const seed = params.get('seed');
+const game = seed ? Game.new_with_seed(SIZE, SIZE, Number(seed)) : Game.new(SIZE, SIZE);
+// Grids live in IndexedDB, the game only gets the ones it needs
+game.use_staging_store();
+const generator = params.get('generator');
+if (generator && !game.use_generator(generator)) {
+    console.log("unknown generator: " + generator);
+}
+// Each world (seed and generator) keeps its own header and grids in
+// IndexedDB, so opening a shared seed never mixes in another world's grids
+const worldPrefix = `world_${game.seed()}_${game.generator_name()}_`;
 const width = game.width();
 const height = game.height();
 
@@ -74,34 +86,45 @@ function idbGet(key) {
     });
 }
 
+// Restore the player etc. before any grid is streamed in
+async function loadHeader() {
+    const header = await idbGet(worldPrefix + 'header');
+    if (header) {
+        game.store_header(header);
+    }
+}
+
 async function loadAndSave() {
 
-    // Load grids from IndexedDB
+    // Make grids stored in IndexedDB available to the game before it needs them
     let grids_to_load = game.get_missing_grids();
     for (const grid_index of grids_to_load) {
-        console.log("loading grid: " + grid_index_name(grid_index));
-        const grid = await idbGet(`grid_${grid_index_name(grid_index)}`);
+        const grid = await idbGet(`${worldPrefix}grid_${grid_index_name(grid_index)}`);
         if (grid) {
-            game.load_grid(grid_index, grid);
+            console.log("loading grid: " + grid_index_name(grid_index));
+            game.store_grid(grid_index, grid);
         } else {
-            game.generate_grid(grid_index);
+            game.no_stored_grid(grid_index);
         }
     }
 
-    let loaded_grids = game.get_loaded_grids();
+    // The game loads/generates, stores and drops grids itself. Only grids
+    // modified since they were last stored are returned for saving.
+    let saved_grids = game.stream();
     const savePromises = [];
-    for (const grid_index of loaded_grids) {
-        console.log("saving (dropping) grid: " + grid_index_name(grid_index));
-        const grid = game.save_grid(grid_index);
+    for (const grid_index of saved_grids) {
+        console.log("saving grid: " + grid_index_name(grid_index));
+        const grid = game.stored_grid(grid_index);
         if (grid) {
             // Collect promises instead of awaiting
-            savePromises.push(idbSet(`grid_${grid_index_name(grid_index)}`, grid));
+            savePromises.push(idbSet(`${worldPrefix}grid_${grid_index_name(grid_index)}`, grid));
         }
     }
-    let droppable_grids = game.get_droppable_grids();
-    for (const grid_index of droppable_grids) {
-        console.log("dropping grid: " + grid_index_name(grid_index));
-        game.drop_grid(grid_index);
+
+    // The world header (player, seed, ...) is saved on every pass
+    const header = game.stored_header();
+    if (header) {
+        savePromises.push(idbSet(worldPrefix + 'header', header));
     }
 
     // Wait for all saves to complete
@@ -162,8 +185,12 @@ function resave() {
         setTimeout(resave, 5000);
     });
 }
-resave();
-renderLoop();
+// Only start ticking once the header is applied and the grids around the
+// player are passed in, the game doesn't generate grids it wasn't told about
+loadHeader().then(loadAndSave).then(() => {
+    setTimeout(resave, 5000);
+    renderLoop();
+});
 
 
 canvas.onmousemove = (e) => {